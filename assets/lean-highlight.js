@@ -0,0 +1,54 @@
+// Lean 4 syntax highlighting for mdbook, installed by `lean2md init`.
+// mdbook highlights code blocks before additional scripts run, so Lean blocks are
+// highlighted again once the language is registered.
+(function () {
+    if (typeof hljs === "undefined") {
+        return;
+    }
+
+    hljs.registerLanguage("lean", function (hljs) {
+        var KEYWORDS = {
+            keyword:
+                "def theorem lemma example abbrev instance structure class inductive " +
+                "where extends deriving namespace section end open export variable universe " +
+                "import noncomputable partial unsafe private protected mutual " +
+                "fun let have show from by do then else if match with at in " +
+                "calc return for unless mut set_option attribute macro syntax " +
+                "macro_rules elab notation infix infixl infixr prefix postfix",
+            built_in:
+                "Type Prop Sort Nat Int String Bool List Array Option IO Unit " +
+                "rfl simp intro intros apply exact constructor cases induction rw " +
+                "omega decide assumption contradiction exists use",
+            literal: "true false",
+        };
+
+        var NESTED_COMMENT = hljs.COMMENT(/\/-/, /-\//, { contains: ["self"] });
+
+        return {
+            name: "Lean",
+            aliases: ["lean4"],
+            keywords: KEYWORDS,
+            contains: [
+                hljs.COMMENT("--", "$"),
+                NESTED_COMMENT,
+                hljs.QUOTE_STRING_MODE,
+                { className: "string", begin: /'(\\.|[^'\\])'/ },
+                { className: "number", begin: /\b\d+(\.\d+)?\b/ },
+                { className: "meta", begin: /#[a-zA-Z_]+/ },
+                { className: "meta", begin: /@\[/, end: /\]/ },
+                { className: "symbol", begin: /[∀∃λ→←↔∧∨¬≠≤≥×∘⟨⟩]/ },
+            ],
+        };
+    });
+
+    var blocks = document.querySelectorAll("code.language-lean, code.language-lean4");
+    Array.prototype.forEach.call(blocks, function (block) {
+        block.classList.remove("hljs");
+        block.innerHTML = block.textContent
+            .replace(/&/g, "&amp;")
+            .replace(/</g, "&lt;")
+            .replace(/>/g, "&gt;");
+        hljs.highlightBlock(block);
+        block.classList.add("hljs");
+    });
+})();
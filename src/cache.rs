@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::lean2md_core::Options;
+
+/// Name of the manifest file kept in the target directory
+pub const MANIFEST_FILE: &str = ".lean2md-manifest.json";
+
+/// Record of a directory build, used to skip sources that have not changed since and to
+/// find generated files that no source produces any more
+///
+/// A cached entry is only trusted if the tool version and the options are the same as
+/// for the build that wrote the manifest.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// Version of lean2md that wrote the manifest
+    pub version: String,
+    /// Hash of the conversion options used
+    pub options_hash: String,
+    /// Hash of what pages know about the other sources, e.g. for links between them
+    #[serde(default)]
+    pub index_hash: String,
+    /// Converted sources, keyed by their path relative to the source directory
+    pub sources: BTreeMap<String, SourceEntry>,
+    /// Previously generated files (relative to the target directory) that no source
+    /// produces any more and that have not been pruned yet
+    #[serde(default)]
+    pub orphaned: Vec<String>,
+}
+
+/// What the manifest remembers about one converted source file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SourceEntry {
+    /// Hash of the source file content
+    pub hash: String,
+    /// Generated files, relative to the target directory
+    pub outputs: Vec<String>,
+}
+
+impl Manifest {
+    /// Loads the manifest of `tgt_dir`, returning an empty one if there is none
+    pub fn load(tgt_dir: &Path) -> Manifest {
+        fs::read_to_string(tgt_dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str::<Manifest>(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the cached entries were produced by this version with the same options and
+    /// source index
+    pub fn matches(&self, options: &Options, index_hash: &str) -> bool {
+        self.version == env!("CARGO_PKG_VERSION")
+            && self.options_hash == options_hash(options)
+            && self.index_hash == index_hash
+    }
+
+    /// Creates an empty manifest for the current version, options and source index
+    pub fn new(options: &Options, index_hash: String) -> Manifest {
+        Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            options_hash: options_hash(options),
+            index_hash,
+            sources: BTreeMap::new(),
+            orphaned: Vec::new(),
+        }
+    }
+
+    /// Writes the manifest to `tgt_dir`
+    pub fn save(&self, tgt_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(
+            tgt_dir.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(self)? + "\n",
+        )?;
+        Ok(())
+    }
+
+    /// All generated files recorded in the manifest, including orphaned ones
+    pub fn outputs(&self) -> BTreeSet<&String> {
+        self.sources
+            .values()
+            .flat_map(|entry| &entry.outputs)
+            .chain(&self.orphaned)
+            .collect()
+    }
+
+    /// Recorded outputs, including orphaned ones, that are not among `current`
+    pub fn orphaned_outputs(&self, current: &BTreeSet<&String>) -> Vec<String> {
+        self.outputs()
+            .into_iter()
+            .filter(|output| !current.contains(output))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether `source` is recorded with the given hash and all its outputs still exist
+    pub fn is_fresh(&self, source: &str, hash: &str, tgt_dir: &Path) -> bool {
+        self.sources.get(source).is_some_and(|entry| {
+            entry.hash == hash
+                && entry
+                    .outputs
+                    .iter()
+                    .all(|output| tgt_dir.join(output).is_file())
+        })
+    }
+}
+
+/// Hex-encoded SHA-256 hash of `data`
+pub(crate) fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Hash of the options that influence the generated files
+///
+/// The options are serialised field by field, so the hash only changes with the options
+/// themselves and not with how they are stored or printed.
+fn options_hash(options: &Options) -> String {
+    let options = serde_json::json!({
+        "quiz_mode": options.quiz_mode,
+        "quiz_dir": options.quiz_dir,
+        "lean_check_dir": options.lean_check_dir,
+        "admonish_kind": options.admonish.kind,
+        "admonish_title": options.admonish.title,
+        "admonish_collapsible": options.admonish.collapsible,
+        "namespaces": options.namespaces,
+        "toc": options.toc,
+        "heading_shift": options.heading_shift,
+        "numbering": options.numbering,
+        "include": options.include,
+        "exclude": options.exclude,
+        "no_ignore": options.no_ignore,
+        "front_matter": options.front_matter,
+        "summary": options.summary,
+        "import_links": options.import_links,
+        "auto_link": options.auto_link,
+        "declaration_index": options.declaration_index,
+    });
+    hash(options.to_string().as_bytes())
+}
+
+/// Key under which a source is stored in the manifest
+pub(crate) fn source_key(relative: &Path) -> String {
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// Path of an output as stored in the manifest
+///
+/// Outputs in the target directory are stored relative to it; others, such as quizzes in a
+/// separate quiz directory, by their absolute path.
+pub(crate) fn output_key(tgt_dir: &Path, output: &Path) -> String {
+    let key = crate::lean2md_core::relative_link(tgt_dir, output);
+    if Path::new(&key)
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        std::path::absolute(output)
+            .unwrap_or_else(|_| output.to_path_buf())
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        key
+    }
+}
+
+/// The path of an output stored in the manifest under `key`, together with the directory
+/// it was generated into
+///
+/// Relative keys belong to the target directory, absolute ones to the configured quiz or
+/// quiz check directory. Returns `None` for keys that lead anywhere else, such as
+/// `../notes.md`, so that a tampered manifest can never make lean2md touch other files.
+pub(crate) fn output_path(
+    tgt_dir: &Path,
+    key: &str,
+    options: &Options,
+) -> Option<(PathBuf, PathBuf)> {
+    let path = Path::new(key);
+    if path
+        .components()
+        .any(|component| matches!(component, Component::ParentDir | Component::CurDir))
+    {
+        return None;
+    }
+
+    if path.is_relative() {
+        return Some((tgt_dir.join(path), tgt_dir.to_path_buf()));
+    }
+    [&options.quiz_dir, &options.lean_check_dir]
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| std::path::absolute(dir).ok())
+        .find(|dir| path.starts_with(dir))
+        .map(|dir| (path.to_path_buf(), dir))
+}
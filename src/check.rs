@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use similar::TextDiff;
+
+use crate::cache::{self, Manifest, MANIFEST_FILE};
+use crate::lean2md_core::{plan_directory, Options, OutputFile};
+
+/// Compares the output of converting `src_dir` with the files in `tgt_dir`
+///
+/// Runs the whole conversion in memory and prints a unified diff for every generated file
+/// (pages, quizzes and quiz checks) that is missing or differs from the file on disk. Files
+/// an earlier build generated for sources or quizzes that no longer exist are out of date
+/// too, as long as they are still on disk. Nothing is written to the filesystem.
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `tgt_dir` - Path to the target directory holding the generated Markdown
+/// * `options` - Conversion options
+/// * `out` - Where the diffs and the summary are printed
+///
+/// # Returns
+///
+/// `true` if every generated file is up to date, or an error if the conversion fails
+pub fn check_directory(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    options: &Options,
+    out: &mut dyn Write,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let plans = plan_directory(src_dir, tgt_dir, options)?;
+    let mut stale = 0;
+
+    let outputs: Vec<_> = plans.iter().flat_map(|plan| &plan.outputs).collect();
+    for output in &outputs {
+        let (current, old_header) = match fs::read_to_string(&output.path) {
+            Ok(current) => (current, output.path.display().to_string()),
+            Err(_) => (String::new(), "/dev/null".to_string()),
+        };
+        // Line endings do not make a file stale
+        if current.replace("\r\n", "\n") == output.content {
+            continue;
+        }
+
+        stale += 1;
+        let new_header = output.path.display().to_string();
+        write!(
+            out,
+            "{}",
+            TextDiff::from_lines(&current, &output.content)
+                .unified_diff()
+                .header(&old_header, &new_header)
+        )?;
+    }
+
+    // Leftovers of deleted sources and quizzes, which a build with --prune removes
+    for path in leftover_outputs(tgt_dir, &outputs, options) {
+        stale += 1;
+        let current = fs::read_to_string(&path).unwrap_or_default();
+        write!(
+            out,
+            "{}",
+            TextDiff::from_lines(current.as_str(), "")
+                .unified_diff()
+                .header(&path.display().to_string(), "/dev/null")
+        )?;
+    }
+
+    if stale == 0 {
+        writeln!(out, "All generated files are up to date")?;
+    } else {
+        writeln!(
+            out,
+            "{} generated file(s) are out of date; run lean2md build to update them",
+            stale
+        )?;
+    }
+
+    Ok(stale == 0)
+}
+
+/// Reports what converting `src_dir` into `tgt_dir` would do, without writing anything
+///
+/// Lists every directory that would be created and every generated file (pages, quizzes and
+/// quiz checks) with whether it would be created, overwritten with different content or
+/// left unchanged, followed by the build manifest. With `options.prune`, the files of
+/// deleted sources and quizzes that would be removed are listed too.
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `tgt_dir` - Path to the target directory where Markdown files would be created
+/// * `options` - Conversion options
+/// * `out` - Where the report is printed
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn dry_run_directory(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    options: &Options,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let plans = plan_directory(src_dir, tgt_dir, options)?;
+    let outputs: Vec<_> = plans.iter().flat_map(|plan| &plan.outputs).collect();
+
+    // Directories that do not exist yet, parents before children
+    let mut new_dirs = BTreeSet::new();
+    let parents = outputs.iter().filter_map(|output| output.path.parent());
+    for dir in std::iter::once(tgt_dir).chain(parents) {
+        for ancestor in dir.ancestors() {
+            if ancestor.as_os_str().is_empty() || ancestor.exists() {
+                break;
+            }
+            new_dirs.insert(ancestor.to_path_buf());
+        }
+    }
+    for dir in &new_dirs {
+        writeln!(out, "create dir  {}", dir.display())?;
+    }
+
+    let (mut created, mut overwritten) = (0, 0);
+    for output in &outputs {
+        let status = match fs::read_to_string(&output.path) {
+            Err(_) => {
+                created += 1;
+                "create     "
+            }
+            Ok(current) if current.replace("\r\n", "\n") != output.content => {
+                overwritten += 1;
+                "overwrite  "
+            }
+            Ok(_) => "unchanged  ",
+        };
+        writeln!(out, "{} {}", status, output.path.display())?;
+    }
+
+    let manifest_path = tgt_dir.join(MANIFEST_FILE);
+    let status = if manifest_path.exists() {
+        "update     "
+    } else {
+        "create     "
+    };
+    writeln!(out, "{} {}", status, manifest_path.display())?;
+
+    let mut removed = 0;
+    if options.prune {
+        for path in leftover_outputs(tgt_dir, &outputs, options) {
+            removed += 1;
+            writeln!(out, "remove      {}", path.display())?;
+        }
+    }
+
+    write!(
+        out,
+        "Dry run: {} file(s) would be created, {} overwritten with different content, {} unchanged",
+        created,
+        overwritten,
+        outputs.len() - created - overwritten
+    )?;
+    if options.prune {
+        write!(out, ", {} removed", removed)?;
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// Files generated by earlier builds into `tgt_dir` that are still on disk although no
+/// source produces them any more
+fn leftover_outputs(tgt_dir: &Path, outputs: &[&OutputFile], options: &Options) -> Vec<PathBuf> {
+    let keys: Vec<String> = outputs
+        .iter()
+        .map(|output| cache::output_key(tgt_dir, &output.path))
+        .collect();
+    Manifest::load(tgt_dir)
+        .orphaned_outputs(&keys.iter().collect())
+        .iter()
+        .filter_map(|key| cache::output_path(tgt_dir, key, options))
+        .map(|(path, _)| path)
+        .filter(|path| path.is_file())
+        .collect()
+}
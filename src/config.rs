@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::headings::Numbering;
+use crate::lean2md_core::{NamespaceStyle, Options};
+use crate::quiz::QuizMode;
+
+/// Name of the dedicated configuration file
+pub const CONFIG_FILE: &str = "lean2md.toml";
+
+/// Name of the Lake manifest that may carry a `[lean2md]` section instead
+const LAKEFILE: &str = "lakefile.toml";
+
+/// Project configuration read from `lean2md.toml` or the `[lean2md]` section of `lakefile.toml`
+///
+/// Relative paths are resolved against the directory containing the configuration file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default source directory for `lean2md build`
+    pub src: Option<PathBuf>,
+    /// Default target directory for `lean2md build`
+    pub tgt: Option<PathBuf>,
+    /// How quizzes are emitted
+    pub quiz_mode: Option<QuizMode>,
+    /// Directory for quiz TOML files
+    pub quiz_dir: Option<PathBuf>,
+    /// Directory for the `.lean` files checking Lean quiz answers
+    pub lean_check_dir: Option<PathBuf>,
+    /// Admonish block defaults
+    pub admonish: AdmonishConfig,
+    /// How namespaces are shown on the generated pages
+    pub namespaces: Option<NamespaceStyle>,
+    /// Whether every page gets a table of contents
+    pub toc: Option<bool>,
+    /// Number of levels headings in the prose are moved down by
+    pub heading_shift: Option<usize>,
+    /// How headings in the prose are numbered
+    pub numbering: Option<Numbering>,
+    /// Glob patterns of the files and folders (relative to the source directory) to convert
+    pub include: Vec<String>,
+    /// Glob patterns of the files and folders (relative to the source directory) to skip
+    pub exclude: Vec<String>,
+    /// Whether to convert files ignored by `.gitignore` or `.lean2mdignore` files
+    pub no_ignore: Option<bool>,
+    /// File whose content is inserted at the top of every generated page
+    pub front_matter: Option<PathBuf>,
+    /// Whether to generate an mdbook `SUMMARY.md` in directory builds
+    pub summary: Option<bool>,
+    /// Whether pages start with links to the pages of the modules they import
+    pub import_links: Option<bool>,
+    /// Whether inline code naming a declaration links to its definition
+    pub auto_link: Option<bool>,
+    /// Whether to generate a page listing all declarations
+    pub declaration_index: Option<bool>,
+    /// Number of files converted in parallel
+    pub jobs: Option<usize>,
+    /// Directory the configuration was loaded from
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+/// The `[admonish]` table of the configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdmonishConfig {
+    /// The admonition kind, e.g. `abstract`, `note` or `tip`
+    pub kind: Option<String>,
+    /// The title shown above the block
+    pub title: Option<String>,
+    /// Whether the block can be collapsed
+    pub collapsible: Option<bool>,
+}
+
+/// The parts of `lakefile.toml` relevant to lean2md
+#[derive(Deserialize)]
+struct Lakefile {
+    lean2md: Option<Config>,
+}
+
+impl Config {
+    /// Loads a configuration file
+    ///
+    /// A `lakefile.toml` is read from its `[lean2md]` section (an empty configuration is
+    /// returned if it has none); any other file is read as a `lean2md.toml`.
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        let mut config = if path.file_name().is_some_and(|name| name == LAKEFILE) {
+            toml::from_str::<Lakefile>(&content)
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+                .lean2md
+                .unwrap_or_default()
+        } else {
+            toml::from_str::<Config>(&content).map_err(|e| format!("{}: {}", path.display(), e))?
+        };
+        config.base_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(config)
+    }
+
+    /// Searches `start` and its ancestors for a configuration
+    ///
+    /// In each directory `lean2md.toml` takes precedence over a `lakefile.toml` with a
+    /// `[lean2md]` section. Returns `None` if no configuration is found.
+    pub fn discover(start: &Path) -> Result<Option<Config>, Box<dyn std::error::Error>> {
+        let start = std::path::absolute(start)?;
+
+        for dir in start.ancestors() {
+            let config_file = dir.join(CONFIG_FILE);
+            if config_file.is_file() {
+                return Ok(Some(Config::load(&config_file)?));
+            }
+
+            let lakefile = dir.join(LAKEFILE);
+            if lakefile.is_file() {
+                let content = fs::read_to_string(&lakefile)?;
+                let lakefile_config = toml::from_str::<Lakefile>(&content)
+                    .map_err(|e| format!("{}: {}", lakefile.display(), e))?;
+                if let Some(mut config) = lakefile_config.lean2md {
+                    config.base_dir = dir.to_path_buf();
+                    return Ok(Some(config));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Resolves a path from the configuration against its directory
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.base_dir.join(path)
+    }
+
+    /// Copies every setting present in the configuration into `options`
+    ///
+    /// Fails if the front matter file cannot be read.
+    pub fn apply(&self, options: &mut Options) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(mode) = self.quiz_mode {
+            options.quiz_mode = mode;
+        }
+        if let Some(dir) = &self.quiz_dir {
+            options.quiz_dir = Some(self.resolve(dir));
+        }
+        if let Some(dir) = &self.lean_check_dir {
+            options.lean_check_dir = Some(self.resolve(dir));
+        }
+        if let Some(kind) = &self.admonish.kind {
+            options.admonish.kind = kind.clone();
+        }
+        if let Some(title) = &self.admonish.title {
+            options.admonish.title = title.clone();
+        }
+        if let Some(collapsible) = self.admonish.collapsible {
+            options.admonish.collapsible = collapsible;
+        }
+        if let Some(style) = self.namespaces {
+            options.namespaces = style;
+        }
+        if let Some(toc) = self.toc {
+            options.toc = toc;
+        }
+        if let Some(shift) = self.heading_shift {
+            options.heading_shift = shift;
+        }
+        if let Some(numbering) = self.numbering {
+            options.numbering = numbering;
+        }
+        options.include.extend(self.include.iter().cloned());
+        options.exclude.extend(self.exclude.iter().cloned());
+        if let Some(no_ignore) = self.no_ignore {
+            options.no_ignore = no_ignore;
+        }
+        if let Some(path) = &self.front_matter {
+            let path = self.resolve(path);
+            let front_matter =
+                fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            options.front_matter = Some(front_matter);
+        }
+        if let Some(summary) = self.summary {
+            options.summary = summary;
+        }
+        if let Some(import_links) = self.import_links {
+            options.import_links = import_links;
+        }
+        if let Some(auto_link) = self.auto_link {
+            options.auto_link = auto_link;
+        }
+        if let Some(declaration_index) = self.declaration_index {
+            options.declaration_index = declaration_index;
+        }
+        if let Some(jobs) = self.jobs {
+            options.jobs = Some(jobs);
+        }
+
+        Ok(())
+    }
+}
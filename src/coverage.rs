@@ -0,0 +1,156 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::index::file_declarations;
+use crate::lean2md_core::{build_blocks_with_options, source_files, Options};
+
+/// Documentation coverage of one Lean file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileCoverage {
+    /// Path of the file relative to the source directory
+    pub file: PathBuf,
+    /// Number of public (not `private`) top-level declarations
+    pub declarations: usize,
+    /// Number of public declarations with a `/-- ... -/` docstring
+    pub documented: usize,
+    /// Names of the public declarations without a docstring
+    pub undocumented: Vec<String>,
+    /// Non-empty lines of prose from `/- ... -/` comments
+    pub prose_lines: usize,
+    /// Non-empty lines of code shown on the page
+    pub code_lines: usize,
+    /// Prose lines per code line, or `None` if the file shows no code
+    pub prose_to_code: Option<f64>,
+}
+
+/// Documentation coverage of a source tree
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageReport {
+    /// Coverage of every converted file, in path order
+    pub files: Vec<FileCoverage>,
+    /// Number of public declarations in all files
+    pub declarations: usize,
+    /// Number of public declarations with a docstring in all files
+    pub documented: usize,
+    /// Percentage of public declarations with a docstring (100 if there are none)
+    pub coverage: f64,
+}
+
+/// Measures how well the Lean files of a directory are documented
+///
+/// Parses every file a directory build would convert, counting its public declarations
+/// with and without docstrings, and its lines of prose and code. A docstring marked with
+/// `--+` counts as the docstring of the declaration below it.
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `options` - Conversion options, which select the files and how they are parsed
+///
+/// # Returns
+///
+/// The coverage report, or an error if a file cannot be read or parsed
+pub fn coverage_report(
+    src_dir: &Path,
+    options: &Options,
+) -> Result<CoverageReport, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+
+    for (src_file, relative) in source_files(src_dir, options)? {
+        let content = fs::read_to_string(&src_file)?;
+        let (blocks, _) = build_blocks_with_options(&content, options)
+            .map_err(|e| format!("{}: {}", src_file.display(), e))?;
+
+        let declarations: Vec<_> = file_declarations(&blocks, &relative)
+            .into_iter()
+            .filter(|declaration| !declaration.private)
+            .collect();
+        let undocumented: Vec<String> = declarations
+            .iter()
+            .filter(|declaration| declaration.doc.is_none())
+            .map(|declaration| declaration.name.clone())
+            .collect();
+
+        let count_lines = |is_code: bool| -> usize {
+            blocks
+                .iter()
+                .filter(|block| block.is_code == is_code && !block.is_admonish)
+                .flat_map(|block| block.content.lines())
+                .filter(|line| !line.trim().is_empty())
+                .count()
+        };
+        let prose_lines = count_lines(false);
+        let code_lines = count_lines(true);
+
+        files.push(FileCoverage {
+            file: relative,
+            declarations: declarations.len(),
+            documented: declarations.len() - undocumented.len(),
+            undocumented,
+            prose_lines,
+            code_lines,
+            prose_to_code: (code_lines > 0).then(|| prose_lines as f64 / code_lines as f64),
+        });
+    }
+
+    let declarations = files.iter().map(|file| file.declarations).sum();
+    let documented = files.iter().map(|file| file.documented).sum();
+    Ok(CoverageReport {
+        files,
+        declarations,
+        documented,
+        coverage: percentage(documented, declarations),
+    })
+}
+
+impl CoverageReport {
+    /// Prints the report as a human-readable list of files
+    ///
+    /// Each file shows its documented declarations and prose-to-code ratio, followed by
+    /// the declarations missing a docstring and a note if it has no prose at all.
+    pub fn write_text(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for file in &self.files {
+            let ratio = file
+                .prose_to_code
+                .map_or("-".to_string(), |ratio| format!("{:.2}", ratio));
+            writeln!(
+                out,
+                "{}: {}/{} declarations documented, prose/code {}",
+                file.file.display(),
+                file.documented,
+                file.declarations,
+                ratio
+            )?;
+            if !file.undocumented.is_empty() {
+                writeln!(out, "  undocumented: {}", file.undocumented.join(", "))?;
+            }
+            if file.prose_lines == 0 {
+                writeln!(out, "  no prose")?;
+            }
+        }
+
+        writeln!(
+            out,
+            "Total: {}/{} declarations documented ({:.1}%)",
+            self.documented, self.declarations, self.coverage
+        )
+    }
+
+    /// Prints the report as JSON
+    pub fn write_json(&self, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+        serde_json::to_writer_pretty(&mut *out, self)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+fn percentage(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * part as f64 / total as f64
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Marker line replaced by a table of contents of the page
+pub const TOC_MARKER: &str = "--@toc";
+
+/// How the section headings of the prose are numbered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Numbering {
+    /// Headings are not numbered
+    #[default]
+    Off,
+    /// Every page is numbered on its own, starting at 1 for its highest heading level
+    File,
+    /// Numbers continue from page to page in the order of the book, `#` being a chapter
+    Book,
+}
+
+impl FromStr for Numbering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Numbering::Off),
+            "file" => Ok(Numbering::File),
+            "book" => Ok(Numbering::Book),
+            _ => Err(format!(
+                "Unknown numbering '{}' (expected 'off', 'file' or 'book')",
+                s
+            )),
+        }
+    }
+}
+
+/// The numbers of the last section at each depth, e.g. `[2, 1]` after section 2.1
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SectionNumbers(Vec<usize>);
+
+impl SectionNumbers {
+    /// The number of the next section at `depth` (1 for the top level), e.g. `2.2`
+    fn next(&mut self, depth: usize) -> String {
+        self.0.resize(depth, 0);
+        self.0[depth - 1] += 1;
+        self.0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// A Markdown heading of a generated page
+struct Heading {
+    /// Index of the heading's line in the page
+    line: usize,
+    /// Number of `#` characters
+    level: usize,
+    /// The heading text
+    text: String,
+}
+
+/// The ATX headings of a page, leaving out lines inside fenced blocks
+fn headings(lines: &[&str]) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut in_fence = false;
+
+    for (line_idx, line) in lines.iter().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        if let Some((level, text)) = parse_heading(line) {
+            headings.push(Heading {
+                line: line_idx,
+                level,
+                text: text.to_string(),
+            });
+        }
+    }
+
+    headings
+}
+
+/// The level and text of an ATX heading line such as `## Title`
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    if !(1..=6).contains(&level) || !(text.is_empty() || text.starts_with(' ')) {
+        return None;
+    }
+    let text = text.trim().trim_end_matches('#').trim();
+    (!text.is_empty()).then_some((level, text))
+}
+
+/// The levels of the headings in a block of prose, in order
+pub(crate) fn heading_levels(content: &str) -> Vec<usize> {
+    let lines: Vec<&str> = content.lines().collect();
+    headings(&lines)
+        .iter()
+        .map(|heading| heading.level)
+        .collect()
+}
+
+/// Shifts the headings of a block of prose down by `shift` levels and numbers them
+///
+/// Levels are capped at 6. With `numbering`, each heading is prefixed with the next number
+/// from the section numbers, its depth counted from the given top level.
+pub(crate) fn rewrite_headings(
+    content: &str,
+    shift: usize,
+    mut numbering: Option<(&mut SectionNumbers, usize)>,
+) -> String {
+    let mut in_fence = false;
+    let mut lines = Vec::new();
+
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        match parse_heading(line) {
+            Some((level, text)) if !in_fence => {
+                let hashes = "#".repeat((level + shift).min(6));
+                match &mut numbering {
+                    Some((numbers, top)) => {
+                        let number = numbers.next(level.saturating_sub(*top) + 1);
+                        lines.push(format!("{} {} {}", hashes, number, text));
+                    }
+                    None => lines.push(format!("{} {}", hashes, text)),
+                }
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// The anchor GitHub and mdbook give a heading
+///
+/// Letters are lowercased, spaces become `-`, and everything other than letters, digits,
+/// `-` and `_` is dropped.
+pub(crate) fn slug(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Replaces every `--@toc` marker line of a page by a linked list of its headings
+///
+/// Without a marker, the table of contents is only added if `always` is set, right below
+/// the page title if the page starts with one, and at the top otherwise. A heading that is
+/// the only one of the top level in the page is taken to be its title and left out.
+/// Repeated headings get numbered anchors (`-1`, `-2`, ...) as on GitHub.
+pub(crate) fn insert_toc(markdown: &str, always: bool) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let headings = headings(&lines);
+
+    let markers: Vec<usize> = (0..lines.len())
+        .filter(|&line_idx| lines[line_idx].trim() == TOC_MARKER && !in_fence_at(&lines, line_idx))
+        .collect();
+    let title = title_index(&headings);
+    // The line the table of contents goes before if there is no marker
+    let position = match headings.first() {
+        _ if !markers.is_empty() => None,
+        _ if !always => return markdown.to_string(),
+        Some(heading)
+            if title == Some(0) && lines[..heading.line].iter().all(|l| l.trim().is_empty()) =>
+        {
+            Some(heading.line + 1)
+        }
+        _ => Some(0),
+    };
+
+    let toc = toc_lines(&headings, title);
+    let mut page: Vec<String> = Vec::new();
+    let mut skip_blank = false;
+    for (line_idx, line) in lines.iter().chain([&""]).enumerate() {
+        let is_marker = markers.contains(&line_idx);
+        if is_marker || position == Some(line_idx) {
+            if !toc.is_empty() {
+                if page.last().is_some_and(|line| !line.trim().is_empty()) {
+                    page.push(String::new());
+                }
+                page.extend(toc.iter().cloned());
+                page.push(String::new());
+            }
+            // Neither the table of contents nor a removed marker may leave two blank
+            // lines in a row
+            skip_blank = page.last().is_none_or(|line| line.trim().is_empty());
+            if is_marker {
+                continue;
+            }
+        }
+        if skip_blank && line.trim().is_empty() {
+            skip_blank = false;
+            continue;
+        }
+        skip_blank = false;
+        page.push(line.to_string());
+    }
+
+    page.join("\n").trim_end().to_string() + "\n"
+}
+
+/// The index of the heading taken to be the page title: the only one of the top level
+fn title_index(headings: &[Heading]) -> Option<usize> {
+    let top = headings.iter().map(|h| h.level).min()?;
+    match headings.iter().filter(|h| h.level == top).count() {
+        1 => headings.iter().position(|h| h.level == top),
+        _ => None,
+    }
+}
+
+/// The entries of the table of contents for the headings of a page, leaving out its title
+fn toc_lines(headings: &[Heading], title: Option<usize>) -> Vec<String> {
+    // Anchors are numbered over all headings, including a left out title
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut anchors = Vec::new();
+    for heading in headings {
+        let base = slug(&heading.text);
+        let count = seen.entry(base.clone()).or_insert(0);
+        anchors.push(match *count {
+            0 => base,
+            n => format!("{}-{}", base, n),
+        });
+        *count += 1;
+    }
+
+    let entries: Vec<(&Heading, &String)> = headings
+        .iter()
+        .zip(&anchors)
+        .enumerate()
+        .filter(|(i, _)| Some(*i) != title)
+        .map(|(_, entry)| entry)
+        .collect();
+
+    let base_level = entries.iter().map(|(h, _)| h.level).min().unwrap_or(1);
+    entries
+        .iter()
+        .map(|(heading, anchor)| {
+            format!(
+                "{}- [{}](#{})",
+                "  ".repeat(heading.level - base_level),
+                heading.text,
+                anchor
+            )
+        })
+        .collect()
+}
+
+/// Whether the line `line_idx` lies inside a fenced block
+fn in_fence_at(lines: &[&str], line_idx: usize) -> bool {
+    lines[..line_idx]
+        .iter()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count()
+        % 2
+        == 1
+}
@@ -0,0 +1,568 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::cache;
+use crate::headings::{self, Numbering, SectionNumbers};
+use crate::lean2md_core::{
+    build_blocks, build_blocks_with_options, is_prose, relative_link, Block, Options, OutputFile,
+    OutputKind, Scopes,
+};
+use crate::summary;
+
+/// Keywords introducing the declarations lean2md indexes
+const DECLARATION_KINDS: [&str; 7] = [
+    "def",
+    "theorem",
+    "structure",
+    "inductive",
+    "class",
+    "instance",
+    "abbrev",
+];
+
+/// Headings of the declaration kinds on the declaration index page
+const KIND_TITLES: [&str; 7] = [
+    "Definitions",
+    "Theorems",
+    "Structures",
+    "Inductive Types",
+    "Classes",
+    "Instances",
+    "Abbreviations",
+];
+
+/// Name of the declaration index page generated in the target directory
+pub const DECLARATIONS_FILE: &str = "declarations.md";
+
+/// Modifiers that may precede a declaration keyword
+const MODIFIERS: [&str; 7] = [
+    "private",
+    "protected",
+    "noncomputable",
+    "partial",
+    "unsafe",
+    "nonrec",
+    "scoped",
+];
+
+/// What a directory build knows about all of its sources, used to link pages to each other
+#[derive(Debug, Default)]
+pub(crate) struct SourceIndex {
+    /// Generated page of each source, keyed by module name relative to the source directory
+    modules: BTreeMap<String, PathBuf>,
+    /// Name of the source directory, which imports may start with as the module root
+    root: String,
+    /// Top-level declarations of all sources, in source order
+    declarations: Vec<Declaration>,
+    /// Section numbers each page continues from, if numbered across the book
+    section_numbers: BTreeMap<PathBuf, SectionNumbers>,
+}
+
+/// A named top-level declaration found in the code of a source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Declaration {
+    /// The declaration keyword, e.g. `def` or `theorem`
+    pub kind: &'static str,
+    /// The name as written after the keyword
+    pub name: String,
+    /// The docstring (`/-- ... -/`) of the declaration, if it has one
+    pub doc: Option<String>,
+    /// Whether the declaration is `private`
+    pub private: bool,
+    /// The generated page containing the declaration
+    pub page: PathBuf,
+}
+
+impl Declaration {
+    /// The namespace of the declaration, e.g. `NatPos` for `NatPos.add` (empty at the root)
+    pub fn namespace(&self) -> &str {
+        self.name
+            .rsplit_once('.')
+            .map_or("", |(namespace, _)| namespace)
+    }
+}
+
+impl SourceIndex {
+    /// Indexes the sources of a directory build, whose pages are written to `tgt_dir`
+    ///
+    /// Declarations and section numbers are only collected if an option needs them.
+    /// Sources that cannot be read or parsed contribute no declarations; their conversion
+    /// reports the problem. Numbering sections across the book fails instead, since every
+    /// later page would get wrong numbers.
+    pub fn new(
+        src_dir: &Path,
+        sources: &[(PathBuf, PathBuf)],
+        tgt_dir: &Path,
+        options: &Options,
+    ) -> Result<SourceIndex, Box<dyn std::error::Error>> {
+        let mut index = SourceIndex {
+            root: std::path::absolute(src_dir)?
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        for (src_file, relative) in sources {
+            let module = relative
+                .with_extension("")
+                .iter()
+                .map(|c| c.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join(".");
+            let page = tgt_dir.join(relative).with_extension("md");
+
+            if needs_declarations(options) {
+                let blocks = std::fs::read_to_string(src_file)
+                    .ok()
+                    .and_then(|content| build_blocks(&content).ok())
+                    .map(|(blocks, _)| blocks)
+                    .unwrap_or_default();
+
+                index.declarations.extend(file_declarations(&blocks, &page));
+            }
+
+            index.modules.insert(module, page);
+        }
+
+        if options.numbering == Numbering::Book {
+            index.number_sections(src_dir, sources, tgt_dir, options)?;
+        }
+
+        Ok(index)
+    }
+
+    /// Records the section numbers each page starts from, going through the pages in the
+    /// order of the book's `SUMMARY.md`
+    fn number_sections(
+        &mut self,
+        src_dir: &Path,
+        sources: &[(PathBuf, PathBuf)],
+        tgt_dir: &Path,
+        options: &Options,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut numbers = SectionNumbers::default();
+        for (src_file, page) in summary::book_order(src_dir, tgt_dir, sources)? {
+            self.section_numbers.insert(page, numbers.clone());
+            let content = std::fs::read_to_string(&src_file)
+                .map_err(|e| format!("{}: {}", src_file.display(), e))?;
+            let (blocks, _) = build_blocks_with_options(&content, options)
+                .map_err(|e| format!("{}: {}", src_file.display(), e))?;
+            for block in blocks.iter().filter(|block| is_prose(block)) {
+                headings::rewrite_headings(&block.content, 0, Some((&mut numbers, 1)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The section numbers `page` continues from when numbering across the book
+    pub fn section_numbers(&self, page: &Path) -> Option<SectionNumbers> {
+        self.section_numbers.get(page).cloned()
+    }
+
+    /// Hash of the index, which changes whenever a page may link differently
+    ///
+    /// Empty if none of the options make pages depend on other sources, so that adding
+    /// or removing a source does not invalidate the build cache.
+    pub fn hash(&self, options: &Options) -> String {
+        if options.import_links || options.auto_link || options.numbering == Numbering::Book {
+            cache::hash(format!("{:?}", self).as_bytes())
+        } else {
+            String::new()
+        }
+    }
+
+    /// The generated page of an imported module
+    ///
+    /// Module names in the index are relative to the source directory, so `Book.Basics`
+    /// also matches `Basics.lean` when the sources are in `Book/`. Other prefixes are not
+    /// stripped, so that `Mathlib.Tactic` never links to a local `Tactic.lean`.
+    fn resolve(&self, import: &str) -> Option<&PathBuf> {
+        self.modules.get(import).or_else(|| {
+            let module = import.strip_prefix(&self.root)?.strip_prefix('.')?;
+            self.modules.get(module)
+        })
+    }
+
+    /// A "Depends on" line linking to the pages of the modules imported by `content`
+    ///
+    /// Imports of modules outside the source directory (such as `Mathlib`) are left out,
+    /// and `None` is returned if nothing remains.
+    pub fn depends_on(&self, content: &str, page_dir: &Path) -> Option<String> {
+        let links: Vec<String> = imports(content)
+            .iter()
+            .filter_map(|import| {
+                let page = self.resolve(import)?;
+                Some(format!("[{}]({})", import, relative_link(page_dir, page)))
+            })
+            .collect();
+
+        if links.is_empty() {
+            None
+        } else {
+            Some(format!("**Depends on:** {}", links.join(", ")))
+        }
+    }
+
+    /// The declaration an inline code mention on `page` refers to
+    ///
+    /// A mention matches a declaration of the same name or, failing that, one whose name
+    /// ends with `.` followed by the mention. If several match, one on `page` itself is
+    /// preferred; otherwise the mention is ambiguous and not linked.
+    fn resolve_declaration(&self, mention: &str, page: &Path) -> Option<&Declaration> {
+        let exact: Vec<_> = self
+            .declarations
+            .iter()
+            .filter(|declaration| declaration.name == mention)
+            .collect();
+        let candidates = if exact.is_empty() {
+            let suffix = format!(".{}", mention);
+            self.declarations
+                .iter()
+                .filter(|declaration| declaration.name.ends_with(&suffix))
+                .collect()
+        } else {
+            exact
+        };
+
+        match candidates.as_slice() {
+            [] => None,
+            [declaration] => Some(declaration),
+            _ => candidates
+                .into_iter()
+                .find(|declaration| declaration.page == page),
+        }
+    }
+
+    /// Adds anchors for the declarations of a page and, with `options.auto_link`, links
+    /// inline code in its prose that names a declaration of the directory build
+    ///
+    /// Each code block defining declarations is preceded by an `<a id="Name"></a>` anchor
+    /// per declaration, which is what links to declarations point to.
+    pub fn link_blocks(&self, blocks: Vec<Block>, page: &Path, options: &Options) -> Vec<Block> {
+        let mut linked = Vec::with_capacity(blocks.len());
+
+        for mut block in blocks {
+            if block.quiz_reference.is_some() {
+                linked.push(block);
+            } else if block.is_code {
+                let anchors: Vec<String> = declarations_in(&block.content, &block.namespace, page)
+                    .into_iter()
+                    .map(|declaration| format!("<a id=\"{}\"></a>", declaration.name))
+                    .collect();
+                if !anchors.is_empty() {
+                    linked.push(Block {
+                        content: anchors.join("\n"),
+                        is_code: false,
+                        is_admonish: false,
+                        quiz_reference: None,
+                        namespace: block.namespace.clone(),
+                    });
+                }
+                linked.push(block);
+            } else {
+                if options.auto_link {
+                    block.content = self.link_inline_code(&block.content, page);
+                }
+                linked.push(block);
+            }
+        }
+
+        linked
+    }
+
+    /// Generates the page listing all declarations, grouped by kind and namespace
+    pub fn declaration_page(&self, tgt_dir: &Path) -> OutputFile {
+        let mut content = String::from("# Declaration Index\n");
+
+        for (kind, title) in DECLARATION_KINDS.iter().zip(KIND_TITLES) {
+            let mut by_namespace: BTreeMap<&str, Vec<&Declaration>> = BTreeMap::new();
+            for declaration in self.declarations.iter().filter(|d| d.kind == *kind) {
+                by_namespace
+                    .entry(declaration.namespace())
+                    .or_default()
+                    .push(declaration);
+            }
+            if by_namespace.is_empty() {
+                continue;
+            }
+
+            content.push_str(&format!("\n## {}\n", title));
+            for (namespace, mut declarations) in by_namespace {
+                if namespace.is_empty() {
+                    content.push_str("\n### Root namespace\n\n");
+                } else {
+                    content.push_str(&format!("\n### `{}`\n\n", namespace));
+                }
+
+                declarations.sort_by(|a, b| a.name.cmp(&b.name));
+                for declaration in declarations {
+                    let link = relative_link(tgt_dir, &declaration.page);
+                    content.push_str(&format!(
+                        "- [`{}`]({}#{})",
+                        declaration.name, link, declaration.name
+                    ));
+                    if let Some(summary) = declaration.doc.as_deref().and_then(doc_summary) {
+                        content.push_str(&format!(": {}", summary));
+                    }
+                    content.push('\n');
+                }
+            }
+        }
+
+        OutputFile {
+            path: tgt_dir.join(DECLARATIONS_FILE),
+            content,
+            kind: OutputKind::DeclarationIndex,
+        }
+    }
+
+    /// Turns `` `Name` `` in Markdown prose into a link to the declaration of `Name`,
+    /// leaving fenced code and existing links alone
+    fn link_inline_code(&self, text: &str, page: &Path) -> String {
+        let page_dir = page.parent().unwrap_or(Path::new(""));
+        let mut lines = Vec::new();
+        let mut in_fence = false;
+
+        for line in text.lines() {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+            }
+            if in_fence || line.trim_start().starts_with("```") {
+                lines.push(line.to_string());
+                continue;
+            }
+
+            let mut result = String::new();
+            let mut rest = line;
+            while let Some(start) = rest.find('`') {
+                // Leave ``double backtick`` spans as they are
+                let ticks = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+                if ticks > 1 {
+                    let fence = &rest[start..start + ticks];
+                    let close = rest[start + ticks..]
+                        .find(fence)
+                        .map_or(rest.len(), |i| start + 2 * ticks + i);
+                    result.push_str(&rest[..close]);
+                    rest = &rest[close..];
+                    continue;
+                }
+
+                let after = &rest[start + 1..];
+                let Some(end) = after.find('`') else {
+                    break;
+                };
+                let mention = &after[..end];
+                let span_end = start + end + 2;
+                let in_link = rest[..start].ends_with('[');
+
+                result.push_str(&rest[..start]);
+                match self.resolve_declaration(mention, page) {
+                    Some(declaration) if !in_link && !mention.contains(char::is_whitespace) => {
+                        let target = if declaration.page == page {
+                            String::new()
+                        } else {
+                            relative_link(page_dir, &declaration.page)
+                        };
+                        result
+                            .push_str(&format!("[`{}`]({}#{})", mention, target, declaration.name));
+                    }
+                    _ => result.push_str(&rest[start..span_end]),
+                }
+                rest = &rest[span_end..];
+            }
+            result.push_str(rest);
+            lines.push(result);
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Whether any of the options needs the declarations of all sources
+fn needs_declarations(options: &Options) -> bool {
+    options.auto_link || options.declaration_index
+}
+
+/// The named top-level declarations in the blocks of a Lean file shown on `page`
+///
+/// A docstring marked with `--+` becomes an admonish block right before the code, and is
+/// used as the docstring of the first declaration in that code.
+pub(crate) fn file_declarations(blocks: &[Block], page: &Path) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    let mut admonish = None;
+
+    for block in blocks {
+        if block.is_admonish {
+            admonish = Some(block.content.clone());
+            continue;
+        }
+        if block.is_code {
+            let mut found = declarations_in(&block.content, &block.namespace, page);
+            if let (Some(doc), Some(first)) = (admonish, found.first_mut()) {
+                first.doc.get_or_insert(doc);
+            }
+            declarations.extend(found);
+        }
+        admonish = None;
+    }
+
+    declarations
+}
+
+/// The named top-level declarations in a block of Lean code on `page`, with the
+/// docstrings directly preceding them
+///
+/// Names are qualified with `namespace`, the namespace open at the start of the block,
+/// and with the namespaces the block opens itself.
+pub(crate) fn declarations_in(code: &str, namespace: &str, page: &Path) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    let mut scopes = Scopes::new(namespace);
+    let mut doc: Option<String> = None;
+    let mut open_doc: Option<String> = None;
+
+    for line in code.lines() {
+        // Continue a docstring spanning several lines
+        let mut line = line;
+        if let Some(text) = &mut open_doc {
+            match line.find("-/") {
+                Some(end) => {
+                    text.push_str(&line[..end]);
+                    doc = open_doc.take().map(|text| text.trim().to_string());
+                    line = &line[end + 2..];
+                }
+                None => {
+                    text.push_str(line);
+                    text.push('\n');
+                    continue;
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("/--") {
+            match rest.find("-/") {
+                Some(end) => {
+                    doc = Some(rest[..end].trim().to_string());
+                    line = &rest[end + 2..];
+                }
+                None => {
+                    open_doc = Some(format!("{}\n", rest));
+                    continue;
+                }
+            }
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() || (trimmed.starts_with("@[") && trimmed.ends_with(']')) {
+            continue;
+        }
+        // A declaration may follow its docstring on the same line
+        let line = if line.starts_with(char::is_whitespace) && doc.is_some() {
+            trimmed
+        } else {
+            line
+        };
+
+        scopes.update(line);
+        if let Some((kind, name, private)) = declaration(line) {
+            declarations.push(Declaration {
+                kind,
+                name: qualified_name(&scopes.namespace(), name),
+                doc: doc.take().filter(|doc| !doc.is_empty()),
+                private,
+                page: page.to_path_buf(),
+            });
+        }
+        doc = None;
+    }
+
+    declarations
+}
+
+/// The full name of a declaration named `name` inside `namespace`
+fn qualified_name(namespace: &str, name: String) -> String {
+    match name.strip_prefix("_root_.") {
+        Some(name) => name.to_string(),
+        None if namespace.is_empty() => name,
+        None => format!("{}.{}", namespace, name),
+    }
+}
+
+/// The first paragraph of a docstring on a single line
+fn doc_summary(doc: &str) -> Option<String> {
+    let paragraph = doc.split("\n\n").next()?;
+    let summary = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!summary.is_empty()).then_some(summary)
+}
+
+/// The keyword and name of the declaration starting on `line`, if any, and whether it
+/// is `private`
+///
+/// Only unindented lines count, so that local definitions are not picked up. Anonymous
+/// instances have no name and are skipped.
+fn declaration(line: &str) -> Option<(&'static str, String, bool)> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    // Skip attributes such as `@[simp]` and modifiers such as `private`
+    let mut rest = line.trim();
+    while rest.starts_with("@[") {
+        rest = rest[rest.find(']')? + 1..].trim_start();
+    }
+    let private = rest
+        .split_whitespace()
+        .take_while(|word| MODIFIERS.contains(word))
+        .any(|word| word == "private");
+    let mut words = rest
+        .split_whitespace()
+        .skip_while(|word| MODIFIERS.contains(word));
+
+    let keyword = words.next()?;
+    let kind = DECLARATION_KINDS
+        .into_iter()
+        .find(|kind| *kind == keyword)?;
+    let name: String = words
+        .next()?
+        .chars()
+        .take_while(|c| !matches!(c, '(' | '{' | '[' | ':' | '⦃'))
+        .collect();
+
+    if name.is_empty() {
+        None
+    } else {
+        Some((kind, name, private))
+    }
+}
+
+/// The modules imported at the top of a Lean file, in order
+pub(crate) fn imports(content: &str) -> Vec<String> {
+    let mut modules = Vec::new();
+    let mut in_comment = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if in_comment {
+            in_comment = !line.contains("-/");
+            continue;
+        }
+        if line.starts_with("/-") {
+            in_comment = !line.contains("-/");
+            continue;
+        }
+        if line.is_empty() || line.starts_with("--") || line == "prelude" {
+            continue;
+        }
+
+        // Imports may be marked as in the module system, e.g. `public import Foo`
+        let line = line.split("--").next().unwrap_or_default();
+        let mut words = line
+            .split_whitespace()
+            .skip_while(|word| matches!(*word, "public" | "private" | "meta"));
+        if words.next() != Some("import") {
+            break;
+        }
+        modules.extend(words.filter(|word| *word != "all").map(str::to_string));
+    }
+
+    modules
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::Path;
+
+use crate::config::CONFIG_FILE;
+use crate::lean2md_core::{process_directory_with_options, relative_link, Options};
+
+/// Name of the highlight.js extension for Lean written next to `book.toml`
+pub const LEAN_HIGHLIGHT_FILE: &str = "lean-highlight.js";
+
+const LEAN_HIGHLIGHT: &str = include_str!("../assets/lean-highlight.js");
+
+/// Creates an mdbook project for the Lean sources in `lean_dir` and converts them
+///
+/// Writes to `book_dir`:
+///
+/// - `book.toml` enabling the quiz and admonish preprocessors and Lean highlighting
+/// - `lean2md.toml` pointing lean2md at the sources, so that `lean2md build` run in
+///   `book_dir` updates the book
+/// - the Lean syntax highlighting script
+/// - a `.gitignore` for the rendered book
+///
+/// and then converts `lean_dir` into `book_dir/src`, generating its `SUMMARY.md`.
+///
+/// # Arguments
+///
+/// * `lean_dir` - Path to the directory containing the Lean sources
+/// * `book_dir` - Path to the directory of the new book
+///
+/// # Returns
+///
+/// Result containing `()` on success, or an error if `book_dir` already contains a book or
+/// any of the files written here except `.gitignore`
+pub fn init_book(lean_dir: &Path, book_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !lean_dir.is_dir() {
+        return Err(format!("{} is not a directory", lean_dir.display()).into());
+    }
+    for file in ["book.toml", CONFIG_FILE, LEAN_HIGHLIGHT_FILE] {
+        let path = book_dir.join(file);
+        if path.exists() {
+            return Err(format!("{} already exists", path.display()).into());
+        }
+    }
+
+    let title = std::path::absolute(lean_dir)?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Lean Book".to_string());
+    let src = relative_link(book_dir, lean_dir);
+
+    fs::create_dir_all(book_dir.join("src"))?;
+    write_new(&book_dir.join("book.toml"), &book_toml(&title))?;
+    write_new(&book_dir.join(CONFIG_FILE), &config_toml(&src))?;
+    write_new(&book_dir.join(LEAN_HIGHLIGHT_FILE), LEAN_HIGHLIGHT)?;
+    if !book_dir.join(".gitignore").exists() {
+        write_new(&book_dir.join(".gitignore"), "book\n")?;
+    }
+
+    let options = Options {
+        summary: true,
+        ..Default::default()
+    };
+    process_directory_with_options(lean_dir, &book_dir.join("src"), &options)?;
+
+    println!();
+    println!("Created an mdbook project in {}", book_dir.display());
+    println!("Next steps:");
+    println!("  cargo install mdbook mdbook-quiz mdbook-admonish");
+    println!("  cd {} && mdbook-admonish install", book_dir.display());
+    println!("  lean2md build    # after editing the Lean sources");
+    println!("  mdbook serve");
+
+    Ok(())
+}
+
+fn write_new(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(path, content)?;
+    println!("Created {}", path.display());
+    Ok(())
+}
+
+fn book_toml(title: &str) -> String {
+    format!(
+        "[book]\n\
+         title = {}\n\
+         src = \"src\"\n\
+         \n\
+         [preprocessor.quiz]\n\
+         \n\
+         [preprocessor.admonish]\n\
+         command = \"mdbook-admonish\"\n\
+         \n\
+         [output.html]\n\
+         additional-js = [\"{}\"]\n",
+        toml::Value::String(title.to_string()),
+        LEAN_HIGHLIGHT_FILE
+    )
+}
+
+fn config_toml(src: &str) -> String {
+    format!(
+        "# Settings for lean2md, see https://github.com/fosterfarrell9/lean2md\n\
+         src = {}\n\
+         tgt = \"src\"\n\
+         summary = true\n",
+        toml::Value::String(src.to_string())
+    )
+}
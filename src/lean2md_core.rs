@@ -1,9 +1,21 @@
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-/// A (quiz_name, quiz_content) pair.
-type Quiz = (String, String);
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{self, Manifest};
+use crate::headings::{self, Numbering, SectionNumbers, TOC_MARKER};
+use crate::index::SourceIndex;
+use crate::quiz::{self, Quiz, QuizMode};
+use crate::summary;
+
+/// Name of the file listing sources to skip, in `.gitignore` syntax
+pub const IGNORE_FILE: &str = ".lean2mdignore";
 
 /// The result of parsing: blocks plus extracted quizzes.
 type BlocksResult = Result<(Vec<Block>, Vec<Quiz>), String>;
@@ -19,6 +31,8 @@ pub struct Block {
     pub is_admonish: bool,
     /// Optional reference to a quiz file
     pub quiz_reference: Option<String>,
+    /// The namespace open where the block starts, e.g. `Foo.Bar` (empty at the top level)
+    pub namespace: String,
 }
 
 #[cfg(test)]
@@ -55,6 +69,335 @@ mod tests {
         let (blocks, _) = build_blocks(input).unwrap();
         assert!(blocks[0].content.contains("line 2 --#"));
     }
+
+    #[test]
+    fn test_inline_fenced_quiz() {
+        let input = "/-\n--@quiz:q\n[[questions]]\ntype = \"ShortAnswer\"\n--@quiz-end\n-/";
+        let (blocks, quizzes) = build_blocks(input).unwrap();
+        let options = Options {
+            quiz_mode: QuizMode::Fenced,
+            ..Default::default()
+        };
+        let markdown =
+            merge_blocks(&blocks, &quizzes, "", SectionNumbers::default(), &options).unwrap();
+        assert_eq!(
+            markdown,
+            "```quiz\n[[questions]]\ntype = \"ShortAnswer\"\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_relative_link() {
+        assert_eq!(
+            relative_link(Path::new("out"), Path::new("out/quizzes")),
+            "quizzes"
+        );
+        assert_eq!(
+            relative_link(Path::new("out/sub"), Path::new("out/quizzes")),
+            "../quizzes"
+        );
+        assert_eq!(relative_link(Path::new("out"), Path::new("out")), "");
+        assert_eq!(
+            relative_link(Path::new(""), Path::new("quizzes/quiz.toml")),
+            "quizzes/quiz.toml"
+        );
+    }
+
+    #[test]
+    fn test_namespace_tracking() {
+        let input = "namespace Foo.Bar --#\n\
+                     def a := 1\n\
+                     /- Text -/\n\
+                     end Bar\n\
+                     section\n\
+                     def b := 2\n\
+                     end\n\
+                     end Foo\n\
+                     /- More -/\n\
+                     def c := 3";
+        let (blocks, _) = build_blocks(input).unwrap();
+        let namespaces: Vec<(bool, &str)> = blocks
+            .iter()
+            .map(|block| (block.is_code, block.namespace.as_str()))
+            .collect();
+        assert_eq!(
+            namespaces,
+            [
+                (true, "Foo.Bar"),
+                (false, "Foo.Bar"),
+                (true, "Foo.Bar"),
+                (false, ""),
+                (true, "")
+            ]
+        );
+
+        // Neither the `end` of a `mutual` block nor a docstring line starting with `end`
+        // closes the namespace
+        let input = "namespace Foo\n\
+                     mutual\n\
+                     def a := 1\n\
+                     end\n\
+                     /-- Docstring\n\
+                     end of story -/\n\
+                     def b := 2\n\
+                     /- Text -/\n\
+                     def c := 3\n\
+                     end Foo";
+        let (blocks, _) = build_blocks(input).unwrap();
+        let namespaces: Vec<(bool, &str)> = blocks
+            .iter()
+            .map(|block| (block.is_code, block.namespace.as_str()))
+            .collect();
+        assert_eq!(namespaces, [(true, ""), (false, "Foo"), (true, "Foo")]);
+    }
+}
+
+/// A scope opened by a `namespace`, `section` or `mutual` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+    Namespace(String),
+    Section,
+    /// A `mutual` block, closed by a bare `end`
+    Mutual,
+}
+
+/// The stack of `namespace`, `section` and `mutual` scopes open at a point of a Lean file
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Scopes {
+    stack: Vec<Scope>,
+    /// Number of `/-` comments (including docstrings) open at the end of the last line
+    comment_depth: usize,
+}
+
+impl Scopes {
+    /// The scopes of the namespace `namespace` (empty for the top level)
+    pub(crate) fn new(namespace: &str) -> Scopes {
+        Scopes {
+            stack: namespace
+                .split('.')
+                .filter(|component| !component.is_empty())
+                .map(|component| Scope::Namespace(component.to_string()))
+                .collect(),
+            comment_depth: 0,
+        }
+    }
+
+    /// The current namespace, its components separated by `.`
+    pub(crate) fn namespace(&self) -> String {
+        let components: Vec<&str> = self
+            .stack
+            .iter()
+            .filter_map(|scope| match scope {
+                Scope::Namespace(name) => Some(name.as_str()),
+                Scope::Section | Scope::Mutual => None,
+            })
+            .collect();
+        components.join(".")
+    }
+
+    /// Opens or closes scopes for a line of Lean code
+    ///
+    /// Only unindented `namespace`, `section`, `mutual` and `end` commands are recognised,
+    /// and not on lines starting inside a comment or docstring. `end A.B` closes as many
+    /// namespaces as the name has components.
+    pub(crate) fn update(&mut self, line: &str) {
+        let in_comment = self.comment_depth > 0;
+        self.track_comments(line);
+        if in_comment || line.starts_with(char::is_whitespace) {
+            return;
+        }
+        let code = line.split("--").next().unwrap_or_default();
+        let mut words = code
+            .split_whitespace()
+            .skip_while(|word| matches!(*word, "noncomputable" | "public" | "private"));
+
+        match words.next() {
+            Some("namespace") => {
+                if let Some(name) = words.next() {
+                    self.stack.extend(
+                        name.split('.')
+                            .map(|component| Scope::Namespace(component.to_string())),
+                    );
+                }
+            }
+            Some("section") => self.stack.push(Scope::Section),
+            Some("mutual") => self.stack.push(Scope::Mutual),
+            Some("end") => {
+                let count = match (words.next(), self.stack.last()) {
+                    (Some(name), Some(Scope::Namespace(_))) => name.split('.').count(),
+                    _ => 1,
+                };
+                let len = self.stack.len().saturating_sub(count);
+                self.stack.truncate(len);
+            }
+            _ => {}
+        }
+    }
+
+    /// Follows the `/-` comments, which may be nested, opened and closed on a line
+    fn track_comments(&mut self, line: &str) {
+        let mut rest = line;
+        loop {
+            let (open, close) = (rest.find("/-"), rest.find("-/"));
+            if self.comment_depth == 0 {
+                // Nothing after a line comment counts
+                let line_comment = rest.find("--");
+                match open {
+                    Some(start) if line_comment.is_none_or(|comment| start < comment) => {
+                        self.comment_depth = 1;
+                        rest = &rest[start + 2..];
+                    }
+                    _ => return,
+                }
+            } else {
+                match (open, close) {
+                    (Some(start), Some(end)) if start < end => {
+                        self.comment_depth += 1;
+                        rest = &rest[start + 2..];
+                    }
+                    (_, Some(end)) => {
+                        self.comment_depth -= 1;
+                        rest = &rest[end + 2..];
+                    }
+                    (Some(start), None) => {
+                        self.comment_depth += 1;
+                        rest = &rest[start + 2..];
+                    }
+                    (None, None) => return,
+                }
+            }
+        }
+    }
+}
+
+/// Appearance of the admonish blocks generated for docstrings marked with `--+`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmonishStyle {
+    /// The admonition kind, e.g. `abstract`, `note` or `tip`
+    pub kind: String,
+    /// The title shown above the block
+    pub title: String,
+    /// Whether the block can be collapsed
+    pub collapsible: bool,
+}
+
+impl Default for AdmonishStyle {
+    fn default() -> Self {
+        AdmonishStyle {
+            kind: "abstract".to_string(),
+            title: "Docstring".to_string(),
+            collapsible: false,
+        }
+    }
+}
+
+impl AdmonishStyle {
+    /// The opening fence line of an admonish block, including the trailing newline
+    fn header(&self) -> String {
+        format!(
+            "```admonish {} collapsible = {}, title = \"{}\"\n",
+            self.kind, self.collapsible, self.title
+        )
+    }
+}
+
+/// How the namespace of the code is shown on generated pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamespaceStyle {
+    /// Namespaces are not shown
+    #[default]
+    Off,
+    /// A `## Namespace` heading before the first block of each namespace
+    Headings,
+    /// A line of the namespace components separated by `›` before the first block of each
+    /// namespace
+    Breadcrumbs,
+}
+
+impl FromStr for NamespaceStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(NamespaceStyle::Off),
+            "headings" => Ok(NamespaceStyle::Headings),
+            "breadcrumbs" => Ok(NamespaceStyle::Breadcrumbs),
+            _ => Err(format!(
+                "Unknown namespace style '{}' (expected 'off', 'headings' or 'breadcrumbs')",
+                s
+            )),
+        }
+    }
+}
+
+impl NamespaceStyle {
+    /// The Markdown announcing that the following code is in `namespace`, if any
+    ///
+    /// Headings are shifted down by `shift` levels like the headings of the prose.
+    fn render(self, namespace: &str, shift: usize) -> Option<String> {
+        match self {
+            NamespaceStyle::Off => None,
+            NamespaceStyle::Headings => Some(format!(
+                "{} Namespace `{}`",
+                "#".repeat((2 + shift).min(6)),
+                namespace
+            )),
+            NamespaceStyle::Breadcrumbs => Some(
+                namespace
+                    .split('.')
+                    .map(|component| format!("`{}`", component))
+                    .collect::<Vec<_>>()
+                    .join(" › "),
+            ),
+        }
+    }
+}
+
+/// Options controlling how Lean files are converted
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// How quizzes are emitted (separate TOML files or inline in the Markdown)
+    pub quiz_mode: QuizMode,
+    /// Directory for quiz TOML files; defaults to `quizzes` inside the target directory
+    pub quiz_dir: Option<PathBuf>,
+    /// Directory for the `.lean` files checking Lean quiz answers; defaults to the quiz directory
+    pub lean_check_dir: Option<PathBuf>,
+    /// Appearance of admonish blocks
+    pub admonish: AdmonishStyle,
+    /// How namespaces are shown on the generated pages
+    pub namespaces: NamespaceStyle,
+    /// Add a table of contents to every page, also to those without a `--@toc` marker
+    pub toc: bool,
+    /// Number of levels every heading in the prose is moved down by (`#` becomes `##` for 1)
+    pub heading_shift: usize,
+    /// How the headings in the prose are numbered
+    pub numbering: Numbering,
+    /// Glob patterns (relative to the source directory) of the files and folders to
+    /// convert; all files are converted if there are none
+    pub include: Vec<String>,
+    /// Glob patterns (relative to the source directory) of files and folders to skip
+    pub exclude: Vec<String>,
+    /// Convert files even if a `.gitignore` or `.lean2mdignore` file ignores them
+    pub no_ignore: bool,
+    /// Text inserted at the top of every generated page, e.g. YAML front matter
+    pub front_matter: Option<String>,
+    /// Reconvert every file in directory builds, even if the build cache says it is unchanged
+    pub force: bool,
+    /// Delete files generated by earlier directory builds that no source produces any more
+    pub prune: bool,
+    /// Write the files that converted in directory builds even if others failed
+    pub keep_going: bool,
+    /// Generate an mdbook `SUMMARY.md` in directory builds
+    pub summary: bool,
+    /// Start each page of a directory build with links to the pages of the modules it imports
+    pub import_links: bool,
+    /// Link inline code naming a declaration of a directory build to its definition
+    pub auto_link: bool,
+    /// Generate a page listing all declarations in directory builds
+    pub declaration_index: bool,
+    /// Number of files converted in parallel by directory builds; defaults to one per CPU
+    pub jobs: Option<usize>,
 }
 
 /// Parses a Lean file’s text into output blocks plus any quizzes.
@@ -69,10 +412,18 @@ mod tests {
 ///
 /// On success, returns `(blocks, quizzes)` where:
 /// - `blocks` is a `Vec<Block>` of text/code/admonish/quiz‑reference blocks
-/// - `quizzes` is a `Vec<(String, String)>` of `(quiz_name, quiz_toml_content)` pairs
+/// - `quizzes` is a `Vec<Quiz>` holding each quiz's name, TOML content and source line
 ///
 /// On error, returns a `String` describing what went wrong.
 pub fn build_blocks(content: &str) -> BlocksResult {
+    build_blocks_with_options(content, &Options::default())
+}
+
+/// Parses a Lean file’s text into output blocks plus any quizzes using the given options
+///
+/// Behaves like [`build_blocks`], but formats admonish blocks inside code examples
+/// according to `options.admonish`.
+pub fn build_blocks_with_options(content: &str, options: &Options) -> BlocksResult {
     let mut blocks = Vec::new();
     let mut quizzes = Vec::new();
     let mut current_content = String::new();
@@ -82,10 +433,21 @@ pub fn build_blocks(content: &str) -> BlocksResult {
     let mut in_quiz = false;
     let mut current_quiz_name = String::new();
     let mut current_quiz_content = String::new();
+    let mut current_quiz_line = 0;
+    let mut scopes = Scopes::default();
+    // The namespace open at the first line of the code being collected
+    let mut code_namespace = String::new();
 
-    for line in content.lines() {
+    for (line_idx, line) in content.lines().enumerate() {
         let line = line.trim_end();
 
+        // Track namespaces and sections, including on hidden lines. Comments are followed
+        // on every line, so that commands are only recognised outside of them.
+        if !in_comment_block && current_content.trim().is_empty() {
+            code_namespace = scopes.namespace();
+        }
+        scopes.update(line);
+
         // Check for entering/exiting ignore blocks with --#--
         if line == "--#--" {
             in_ignore_block = !in_ignore_block;
@@ -103,6 +465,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
             if line.starts_with("--@quiz:") && !in_quiz {
                 // Extract quiz name
                 current_quiz_name = line[8..].trim().to_string();
+                current_quiz_line = line_idx + 1;
                 in_quiz = true;
                 current_quiz_content.clear();
                 continue;
@@ -112,7 +475,11 @@ pub fn build_blocks(content: &str) -> BlocksResult {
             if line == "--@quiz-end" && in_quiz {
                 in_quiz = false;
                 // Store the quiz
-                quizzes.push((current_quiz_name.clone(), current_quiz_content.clone()));
+                quizzes.push(Quiz {
+                    name: current_quiz_name.clone(),
+                    content: current_quiz_content.clone(),
+                    line: current_quiz_line,
+                });
                 // Add a block with the quiz reference
                 if !current_content.trim().is_empty() {
                     blocks.push(Block {
@@ -120,6 +487,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                         is_code: false,
                         is_admonish: false,
                         quiz_reference: None,
+                        namespace: scopes.namespace(),
                     });
                     current_content = String::new();
                 }
@@ -128,6 +496,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: false,
                     is_admonish: false,
                     quiz_reference: Some(current_quiz_name.clone()),
+                    namespace: scopes.namespace(),
                 });
                 continue;
             }
@@ -183,9 +552,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     current_content.push_str("```\n\n"); // Close the code block
 
                     // Add the admonish block
-                    current_content.push_str(
-                        "```admonish abstract collapsible = false, title = \"Docstring\"\n",
-                    );
+                    current_content.push_str(&options.admonish.header());
                     current_content.push_str(comment_text.trim());
                     current_content.push_str("\n```\n\n");
 
@@ -229,6 +596,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: false,
                     is_admonish,
                     quiz_reference: None,
+                    namespace: scopes.namespace(),
                 });
 
                 in_comment_block = false;
@@ -255,6 +623,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: true,
                     is_admonish: false,
                     quiz_reference: None,
+                    namespace: code_namespace.clone(),
                 });
             }
 
@@ -269,6 +638,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: false,
                     is_admonish: true, // Mark as admonish block
                     quiz_reference: None,
+                    namespace: scopes.namespace(),
                 });
             }
 
@@ -276,6 +646,28 @@ pub fn build_blocks(content: &str) -> BlocksResult {
             continue;
         }
 
+        // A table of contents marker in code ends the code block
+        if line.trim() == TOC_MARKER {
+            if !current_content.trim().is_empty() {
+                blocks.push(Block {
+                    content: current_content.trim().to_string(),
+                    is_code: true,
+                    is_admonish: false,
+                    quiz_reference: None,
+                    namespace: code_namespace.clone(),
+                });
+            }
+            blocks.push(Block {
+                content: TOC_MARKER.to_string(),
+                is_code: false,
+                is_admonish: false,
+                quiz_reference: None,
+                namespace: scopes.namespace(),
+            });
+            current_content = String::new();
+            continue;
+        }
+
         // Handle single-line docstrings (/--...-/) - treat as code
         if line.starts_with("/--") && line.contains("-/") && !in_comment_block {
             // Add the whole line as code
@@ -297,6 +689,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: true,
                     is_admonish: false,
                     quiz_reference: None,
+                    namespace: code_namespace.clone(),
                 });
             }
 
@@ -311,6 +704,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: false,
                     is_admonish: false,
                     quiz_reference: None,
+                    namespace: scopes.namespace(),
                 });
             }
 
@@ -338,6 +732,7 @@ pub fn build_blocks(content: &str) -> BlocksResult {
                     is_code: true,
                     is_admonish: false,
                     quiz_reference: None,
+                    namespace: code_namespace.clone(),
                 });
             }
 
@@ -360,6 +755,11 @@ pub fn build_blocks(content: &str) -> BlocksResult {
             is_code: !in_comment_block,
             is_admonish: false,
             quiz_reference: None,
+            namespace: if in_comment_block {
+                scopes.namespace()
+            } else {
+                code_namespace.clone()
+            },
         });
     }
 
@@ -371,17 +771,62 @@ pub fn build_blocks(content: &str) -> BlocksResult {
     Ok((blocks, quizzes))
 }
 
-fn merge_blocks(blocks: &[Block]) -> String {
+/// Renders the blocks of a page as Markdown
+///
+/// `numbers` are the section numbers the page continues from, which are only non-zero for
+/// pages after the first in book numbering.
+fn merge_blocks(
+    blocks: &[Block],
+    quizzes: &[Quiz],
+    quiz_link_dir: &str,
+    mut numbers: SectionNumbers,
+    options: &Options,
+) -> Result<String, String> {
     let mut result = String::new();
+    let mut namespace = "";
+
+    // Pages numbered on their own count sections from their highest heading level
+    let top_level = match options.numbering {
+        Numbering::File => blocks
+            .iter()
+            .filter(|block| is_prose(block))
+            .flat_map(|block| headings::heading_levels(&block.content))
+            .min()
+            .unwrap_or(1),
+        _ => 1,
+    };
 
     for block in blocks {
         if block.content.is_empty() && block.quiz_reference.is_none() {
             continue;
         }
 
+        // Announce the namespace when the page moves into a different one
+        if block.quiz_reference.is_none() && block.namespace != namespace {
+            namespace = &block.namespace;
+            if !namespace.is_empty() {
+                if let Some(marker) = options.namespaces.render(namespace, options.heading_shift) {
+                    result.push_str(&marker);
+                    result.push_str("\n\n");
+                }
+            }
+        }
+
         // Handle quiz references
         if let Some(quiz_ref) = &block.quiz_reference {
-            result.push_str(&format!("{{{{#quiz ../quizzes/{}.toml}}}}\n\n", quiz_ref));
+            if options.quiz_mode == QuizMode::File {
+                result.push_str(&format!(
+                    "{{{{#quiz {}{}.toml}}}}\n\n",
+                    quiz_link_dir, quiz_ref
+                ));
+            } else {
+                let quiz = quizzes
+                    .iter()
+                    .find(|quiz| &quiz.name == quiz_ref)
+                    .ok_or_else(|| format!("Missing quiz '{}'", quiz_ref))?;
+                result.push_str(&quiz::render_inline(quiz, options.quiz_mode)?);
+                result.push_str("\n\n");
+            }
             continue;
         }
 
@@ -391,68 +836,424 @@ fn merge_blocks(blocks: &[Block]) -> String {
             result.push_str("\n```\n\n");
         } else if block.is_admonish {
             // Format as admonish block
-            result.push_str("```admonish abstract collapsible = false, title = \"Docstring\"\n");
+            result.push_str(&options.admonish.header());
             result.push_str(&block.content);
             result.push_str("\n```\n\n");
+        } else if options.heading_shift > 0 || options.numbering != Numbering::Off {
+            let numbering =
+                (options.numbering != Numbering::Off).then_some((&mut numbers, top_level));
+            result.push_str(&headings::rewrite_headings(
+                &block.content,
+                options.heading_shift,
+                numbering,
+            ));
+            result.push_str("\n\n");
         } else {
             result.push_str(&block.content);
             result.push_str("\n\n");
         }
     }
 
-    result.trim_end().to_string() + "\n"
+    Ok(headings::insert_toc(
+        &(result.trim_end().to_string() + "\n"),
+        options.toc,
+    ))
 }
 
-/// Processes a single Lean file and converts it to Markdown
+/// Whether a block is prose written in a comment, as opposed to code, docstrings and quizzes
+pub(crate) fn is_prose(block: &Block) -> bool {
+    !block.is_code && !block.is_admonish && block.quiz_reference.is_none()
+}
+
+/// Computes the path of `target` relative to the directory `from_dir`, using `/` separators
+/// so it can be used in Markdown links
+pub(crate) fn relative_link(from_dir: &Path, target: &Path) -> String {
+    // The parent of a bare file name is "", which `absolute` rejects
+    let absolute = |path: &Path| {
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+    };
+    let from = absolute(from_dir);
+    let to = absolute(target);
+
+    let from_components: Vec<_> = from.components().collect();
+    let to_components: Vec<_> = to.components().collect();
+    let common = from_components
+        .iter()
+        .zip(&to_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from_components.len() - common];
+    parts.extend(
+        to_components[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned()),
+    );
+    parts.join("/")
+}
+
+/// The result of converting the text of one Lean file in memory
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    /// The generated Markdown page
+    pub markdown: String,
+    /// Quizzes to be written as TOML files (empty for the inline quiz modes)
+    pub quiz_files: Vec<Quiz>,
+    /// `(quiz_name, content)` pairs of the Lean files checking Lean quiz answers
+    pub lean_checks: Vec<(String, String)>,
+}
+
+/// Converts the text of a Lean file to Markdown without touching the filesystem
 ///
 /// # Arguments
 ///
-/// * `src_file` - Path to the source Lean file
-/// * `tgt_file` - Path to the target Markdown file
+/// * `content` - The full contents of a Lean source file
+/// * `quiz_link_dir` - Prefix for `{{#quiz ...}}` references, i.e. the quiz directory
+///   relative to the generated page followed by `/` (or empty for the page's own directory)
+/// * `options` - Conversion options
 ///
 /// # Returns
 ///
-/// Result containing `()` on success or an error message on failure
-pub fn process_file(src_file: &Path, tgt_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    println!(
-        "Converting {} to {}",
-        src_file.display(),
-        tgt_file.display()
-    );
+/// The generated page and the quiz files belonging to it, or an error message
+pub fn convert_str(
+    content: &str,
+    quiz_link_dir: &str,
+    options: &Options,
+) -> Result<Conversion, String> {
+    convert_linked(content, quiz_link_dir, None, options)
+}
+
+/// Converts the text of a Lean file like [`convert_str`]
+///
+/// `links` holds the index of a directory build and the page being generated, from which
+/// links to other pages are added as `options` ask for.
+fn convert_linked(
+    content: &str,
+    quiz_link_dir: &str,
+    links: Option<(&SourceIndex, &Path)>,
+    options: &Options,
+) -> Result<Conversion, String> {
+    // Parse blocks and extract quizzes
+    let (mut blocks, quizzes) = build_blocks_with_options(content, options)?;
+
+    let mut header = None;
+    if let Some((index, page)) = links {
+        if options.auto_link || options.declaration_index {
+            blocks = index.link_blocks(blocks, page, options);
+        }
+        if options.import_links {
+            header = index.depends_on(content, page.parent().unwrap_or(Path::new("")));
+        }
+    }
+
+    // Turn Lean-checked questions into regular ones and keep their check files
+    let mut lean_checks = Vec::new();
+    let mut expanded_quizzes = Vec::new();
+    for quiz in &quizzes {
+        let (expanded, check) = quiz::expand_lean_questions(quiz)?;
+        if let Some(check) = check {
+            lean_checks.push((expanded.name.clone(), check));
+        }
+        expanded_quizzes.push(expanded);
+    }
+    let quizzes = expanded_quizzes;
+
+    // Generate markdown content
+    let numbers = links
+        .and_then(|(index, page)| index.section_numbers(page))
+        .unwrap_or_default();
+    let mut markdown = merge_blocks(&blocks, &quizzes, quiz_link_dir, numbers, options)?;
+    if let Some(header) = header {
+        markdown = format!("{}\n\n{}", header, markdown);
+    }
+    if let Some(front_matter) = &options.front_matter {
+        markdown = format!("{}\n\n{}", front_matter.trim_end(), markdown);
+    }
 
+    // Inline modes embed the quizzes in the markdown instead of separate files
+    let quiz_files = if options.quiz_mode == QuizMode::File {
+        quizzes
+    } else {
+        Vec::new()
+    };
+
+    Ok(Conversion {
+        markdown,
+        quiz_files,
+        lean_checks,
+    })
+}
+
+impl Conversion {
+    /// Lists the files making up the conversion
+    ///
+    /// The page comes first (omitted if `tgt_file` is `None`), followed by the quiz files in
+    /// `quizzes_dir` and the Lean check files.
+    pub(crate) fn into_outputs(
+        self,
+        tgt_file: Option<&Path>,
+        quizzes_dir: &Path,
+        options: &Options,
+    ) -> Vec<OutputFile> {
+        let mut outputs = Vec::new();
+
+        if let Some(tgt_file) = tgt_file {
+            outputs.push(OutputFile {
+                path: tgt_file.to_path_buf(),
+                content: self.markdown,
+                kind: OutputKind::Page,
+            });
+        }
+
+        for quiz in self.quiz_files {
+            outputs.push(OutputFile {
+                path: quizzes_dir.join(format!("{}.toml", quiz.name)),
+                content: quiz.content,
+                kind: OutputKind::Quiz,
+            });
+        }
+
+        let checks_dir = options.lean_check_dir.as_deref().unwrap_or(quizzes_dir);
+        for (name, check) in self.lean_checks {
+            outputs.push(OutputFile {
+                path: checks_dir.join(format!("{}.lean", name)),
+                content: check,
+                kind: OutputKind::QuizCheck,
+            });
+        }
+
+        outputs
+    }
+}
+
+/// The kind of a generated file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    /// A Markdown page
+    Page,
+    /// A quiz TOML file
+    Quiz,
+    /// A Lean file checking the answers of Lean quiz questions
+    QuizCheck,
+    /// The mdbook table of contents of a directory build
+    Summary,
+    /// The page listing all declarations of a directory build
+    DeclarationIndex,
+}
+
+/// A generated file that has not been written to disk yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFile {
+    /// Where the file is to be written
+    pub path: PathBuf,
+    /// The content of the file
+    pub content: String,
+    /// What the file contains
+    pub kind: OutputKind,
+}
+
+/// Everything generated from one Lean source file
+#[derive(Debug, Clone)]
+pub struct PagePlan {
+    /// The Lean source file
+    pub src_file: PathBuf,
+    /// The generated files, starting with the Markdown page
+    pub outputs: Vec<OutputFile>,
+}
+
+/// Converts one Lean file in memory, placing its quizzes (if any) into `quizzes_dir`
+fn plan_file(
+    src_file: &Path,
+    tgt_file: &Path,
+    quizzes_dir: &Path,
+    index: Option<&SourceIndex>,
+    options: &Options,
+) -> Result<PagePlan, Box<dyn std::error::Error>> {
     // Read the source file
     let content = fs::read_to_string(src_file)?;
+    plan_source(src_file, &content, tgt_file, quizzes_dir, index, options)
+}
+
+/// Converts the already read content of a Lean file in memory
+///
+/// The `index` of a directory build provides the links to other pages.
+fn plan_source(
+    src_file: &Path,
+    content: &str,
+    tgt_file: &Path,
+    quizzes_dir: &Path,
+    index: Option<&SourceIndex>,
+    options: &Options,
+) -> Result<PagePlan, Box<dyn std::error::Error>> {
+    // Quiz references are relative to the page that contains them
+    let page_dir = tgt_file.parent().unwrap_or(Path::new(""));
+    let mut quiz_link_dir = relative_link(page_dir, quizzes_dir);
+    if !quiz_link_dir.is_empty() {
+        quiz_link_dir.push('/');
+    }
+
+    let links = index.map(|index| (index, tgt_file));
+    let conversion = convert_linked(content, &quiz_link_dir, links, options)?;
+
+    Ok(PagePlan {
+        src_file: src_file.to_path_buf(),
+        outputs: conversion.into_outputs(Some(tgt_file), quizzes_dir, options),
+    })
+}
+
+/// Writes generated files to disk, creating directories as needed and reporting each
+/// file to `log`
+///
+/// Files whose content on disk is already identical are not rewritten, so their
+/// modification times stay untouched.
+pub(crate) fn write_outputs(
+    outputs: &[OutputFile],
+    src_file: Option<&Path>,
+    log: &mut dyn Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for output in outputs {
+        match (output.kind, src_file) {
+            (OutputKind::Page, Some(src_file)) => writeln!(
+                log,
+                "Converting {} to {}",
+                src_file.display(),
+                output.path.display()
+            )?,
+            (OutputKind::Page, None) => writeln!(log, "Writing {}", output.path.display())?,
+            (OutputKind::Quiz, _) => writeln!(log, "  Generated quiz: {}", output.path.display())?,
+            (OutputKind::QuizCheck, _) => {
+                writeln!(log, "  Generated quiz check: {}", output.path.display())?
+            }
+            (OutputKind::Summary, _) => {
+                writeln!(log, "Generated summary: {}", output.path.display())?
+            }
+            (OutputKind::DeclarationIndex, _) => writeln!(
+                log,
+                "Generated declaration index: {}",
+                output.path.display()
+            )?,
+        }
+
+        if fs::read(&output.path).is_ok_and(|current| current == output.content.as_bytes()) {
+            continue;
+        }
 
-    // Create parent directory for target file if it doesn't exist
-    if let Some(parent) = tgt_file.parent() {
-        fs::create_dir_all(parent)?;
+        // Only create directories (e.g. for quizzes) once there is something to put in them
+        if let Some(parent) = output.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&output.path)?;
+        file.write_all(output.content.as_bytes())?;
     }
 
-    // Create quizzes directory if needed
-    let quizzes_dir = match tgt_file.parent() {
-        Some(parent) => parent.join("quizzes"),
-        None => PathBuf::from("quizzes"),
+    Ok(())
+}
+
+/// Converts Lean source read from `input` and writes the Markdown to `output`
+///
+/// Used to run lean2md as a filter on standard input and output. Quizzes are either
+/// inlined (see `options.quiz_mode`) or written to `options.quiz_dir`, in which case the
+/// references use that directory as given. Messages about written quiz files go to
+/// standard error so that they do not mix with the Markdown.
+///
+/// # Arguments
+///
+/// * `input` - Reader providing the Lean source
+/// * `output` - Writer receiving the Markdown
+/// * `options` - Conversion options
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn process_stream(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut content = String::new();
+    input.read_to_string(&mut content)?;
+
+    let quiz_link_dir = match &options.quiz_dir {
+        Some(dir) => {
+            let dir = dir.to_string_lossy().replace('\\', "/");
+            format!("{}/", dir.trim_end_matches('/'))
+        }
+        None => String::new(),
     };
-    fs::create_dir_all(&quizzes_dir)?;
 
-    // Process the content
-    let (blocks, quizzes) = build_blocks(&content)?;
-    let markdown = merge_blocks(&blocks);
+    let conversion = convert_str(&content, &quiz_link_dir, options)?;
 
-    // Write quiz files
-    for (name, content) in quizzes {
-        let quiz_path = quizzes_dir.join(format!("{}.toml", name));
-        let mut file = File::create(&quiz_path)?;
-        file.write_all(content.as_bytes())?;
-        println!("  Generated quiz: {}", quiz_path.display());
+    // Without a page location there is no default quiz directory
+    let needs_quiz_dir = !conversion.quiz_files.is_empty()
+        || (!conversion.lean_checks.is_empty() && options.lean_check_dir.is_none());
+    if needs_quiz_dir && options.quiz_dir.is_none() {
+        return Err(
+            "Quizzes read from standard input need an inline --quiz-mode or a --quiz-dir".into(),
+        );
     }
 
-    // Write the markdown file
-    fs::write(tgt_file, markdown)?;
+    let markdown = conversion.markdown.clone();
+    let quizzes_dir = options.quiz_dir.clone().unwrap_or_default();
+    let quiz_outputs = conversion.into_outputs(None, &quizzes_dir, options);
+    write_outputs(&quiz_outputs, None, &mut std::io::stderr())?;
+
+    output.write_all(markdown.as_bytes())?;
+    output.flush()?;
 
     Ok(())
 }
 
+/// Processes a single Lean file and converts it to Markdown
+///
+/// # Arguments
+///
+/// * `src_file` - Path to the source Lean file
+/// * `tgt_file` - Path to the target Markdown file
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn process_file(src_file: &Path, tgt_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    process_file_with_options(src_file, tgt_file, &Options::default())
+}
+
+/// Processes a single Lean file and converts it to Markdown using the given options
+///
+/// Quizzes are written to `options.quiz_dir` or, by default, to a `quizzes` directory
+/// next to the target file.
+///
+/// # Arguments
+///
+/// * `src_file` - Path to the source Lean file
+/// * `tgt_file` - Path to the target Markdown file
+/// * `options` - Conversion options
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn process_file_with_options(
+    src_file: &Path,
+    tgt_file: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let quizzes_dir = match &options.quiz_dir {
+        Some(dir) => dir.clone(),
+        None => match tgt_file.parent() {
+            Some(parent) => parent.join("quizzes"),
+            None => PathBuf::from("quizzes"),
+        },
+    };
+
+    let plan = plan_file(src_file, tgt_file, &quizzes_dir, None, options)
+        .map_err(|e| format!("{}: {}", src_file.display(), e))?;
+    write_outputs(&plan.outputs, Some(&plan.src_file), &mut std::io::stdout())
+}
+
 /// Processes a directory of Lean files and converts them to Markdown
 ///
 /// # Arguments
@@ -464,55 +1265,375 @@ pub fn process_file(src_file: &Path, tgt_file: &Path) -> Result<(), Box<dyn std:
 ///
 /// Result containing `()` on success or an error message on failure
 pub fn process_directory(src_dir: &Path, tgt_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    process_directory_with_options(src_dir, tgt_dir, &Options::default())
+}
+
+/// Processes a directory of Lean files and converts them to Markdown using the given options
+///
+/// Quizzes are written to `options.quiz_dir` or, by default, to a `quizzes` directory
+/// inside the target directory. Files are converted in parallel (see `options.jobs`) and
+/// all of them before anything is written, so a file that fails to convert leaves the
+/// target directory untouched. The errors of all failed files are returned together as
+/// [`ConversionErrors`], and output is reported in source order.
+///
+/// With `options.keep_going`, the files that did convert are written anyway and a summary
+/// of converted, skipped and failed files is printed before the errors are returned.
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `tgt_dir` - Path to the target directory where Markdown files will be created
+/// * `options` - Conversion options
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn process_directory_with_options(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let quizzes_dir = quizzes_dir_for(tgt_dir, options);
+    let sources = source_files(src_dir, options)?;
+    let index = SourceIndex::new(src_dir, &sources, tgt_dir, options)?;
+    let index_hash = index.hash(options);
+
+    let previous = Manifest::load(tgt_dir);
+    let use_cache = !options.force && previous.matches(options, &index_hash);
+    let mut manifest = Manifest::new(options, index_hash);
+    let mut plans = Vec::new();
+    let mut skipped = 0;
+
+    let results = par_map(&sources, options.jobs, |(src_file, relative)| {
+        let content = fs::read(src_file).map_err(|e| e.to_string())?;
+        let key = cache::source_key(relative);
+        let hash = cache::hash(&content);
+
+        // Sources that have not changed since the last build are not converted again
+        if use_cache && previous.is_fresh(&key, &hash, tgt_dir) {
+            let entry = previous.sources[&key].clone();
+            return Ok((key, entry, None));
+        }
+
+        let content = String::from_utf8(content).map_err(|e| e.to_string())?;
+        let md_path = tgt_dir.join(relative).with_extension("md");
+        let plan = plan_source(
+            src_file,
+            &content,
+            &md_path,
+            &quizzes_dir,
+            Some(&index),
+            options,
+        )
+        .map_err(|e| e.to_string())?;
+        let outputs = plan
+            .outputs
+            .iter()
+            .map(|output| cache::output_key(tgt_dir, &output.path))
+            .collect();
+        Ok((key, cache::SourceEntry { hash, outputs }, Some(plan)))
+    })?;
+
+    let mut failures = Vec::new();
+    for ((src_file, relative), result) in sources.iter().zip(results) {
+        match result {
+            Ok((key, entry, plan)) => {
+                manifest.sources.insert(key, entry);
+                match plan {
+                    Some(plan) => plans.push(plan),
+                    None => skipped += 1,
+                }
+            }
+            Err(e) => {
+                // Keep the files of the last successful conversion, but never treat them
+                // as up to date
+                let key = cache::source_key(relative);
+                if let Some(entry) = previous.sources.get(&key) {
+                    let outputs = entry.outputs.clone();
+                    let entry = cache::SourceEntry {
+                        hash: String::new(),
+                        outputs,
+                    };
+                    manifest.sources.insert(key, entry);
+                }
+                failures.push((src_file.clone(), e));
+            }
+        }
+    }
+    if !failures.is_empty() && !options.keep_going {
+        return Err(ConversionErrors { failures }.into());
+    }
+
     // Create the target directory if it doesn't exist
     fs::create_dir_all(tgt_dir)?;
 
-    // Create quizzes directory at the same level as the target directory
-    let parent_dir = tgt_dir.parent().unwrap_or(Path::new("."));
-    let quizzes_dir = parent_dir.join("quizzes");
-    fs::create_dir_all(&quizzes_dir)?;
-
-    for entry in fs::read_dir(src_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.is_dir() {
-            // Recursively process subdirectories
-            let src_subdir = path.file_name().unwrap();
-            let tgt_subdir = tgt_dir.join(src_subdir);
-            process_directory(&path, &tgt_subdir)?;
-        } else if let Some(ext) = path.extension() {
-            if ext == "lean" {
-                // Process lean file
-                let content = fs::read_to_string(&path)?;
-
-                // Get the output path
-                let md_path = tgt_dir.join(path.file_stem().unwrap()).with_extension("md");
-
-                // Display progress information
-                println!("Converting {} to {}", path.display(), md_path.display());
-
-                // Parse blocks and extract quizzes
-                let (blocks, quizzes) = build_blocks(&content)?;
-
-                // Generate markdown content
-                let markdown = merge_blocks(&blocks);
-
-                // Write quiz TOML files
-                for (name, content) in quizzes {
-                    let quiz_path = quizzes_dir.join(format!("{}.toml", name));
-                    let mut file = File::create(&quiz_path)?;
-                    file.write_all(content.as_bytes())?;
-
-                    // Also show when a quiz file is created
-                    println!("  Generated quiz: {}", quiz_path.display());
-                }
+    for plan in &plans {
+        write_outputs(&plan.outputs, Some(&plan.src_file), &mut std::io::stdout())?;
+    }
+    let converted: Vec<_> = sources
+        .iter()
+        .filter(|(src_file, _)| !failures.iter().any(|(failed, _)| failed == src_file))
+        .cloned()
+        .collect();
+    let book_pages = plan_book_pages(src_dir, tgt_dir, &converted, &index, options)?;
+    write_outputs(&book_pages, None, &mut std::io::stdout())?;
+    if options.keep_going {
+        println!("Summary:");
+        println!("  converted  {}", plans.len());
+        println!("  skipped    {}", skipped);
+        println!("  failed     {}", failures.len());
+    } else if skipped > 0 {
+        println!("Skipped {} unchanged file(s)", skipped);
+    }
+
+    // Files generated earlier that no source produces any more
+    let orphaned = previous.orphaned_outputs(&manifest.outputs());
+    if options.prune {
+        prune_outputs(tgt_dir, &orphaned, options)?;
+    } else {
+        manifest.orphaned = orphaned;
+    }
+
+    manifest.save(tgt_dir)?;
 
-                // Create output markdown file
-                fs::write(md_path, markdown)?;
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(ConversionErrors { failures }.into())
+    }
+}
+
+/// Deletes generated files that are no longer produced, along with directories left empty
+///
+/// Only paths recorded in the build manifest are passed in, so files lean2md did not
+/// generate are never touched, and only inside the target, quiz and quiz check directories.
+fn prune_outputs(
+    tgt_dir: &Path,
+    orphaned: &[String],
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for output in orphaned {
+        let Some((path, root)) = cache::output_path(tgt_dir, output, options) else {
+            eprintln!("Not removing {}: outside the output directories", output);
+            continue;
+        };
+        if !path.is_file() {
+            continue;
+        }
+        fs::remove_file(&path)?;
+        println!("Removed stale {}", path.display());
+
+        // Remove directories that became empty, but never the directory the file was
+        // generated into
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            let is_empty = fs::read_dir(current).is_ok_and(|mut entries| entries.next().is_none());
+            if current == root || !current.starts_with(&root) || !is_empty {
+                break;
             }
+            fs::remove_dir(current)?;
+            dir = current.parent();
         }
     }
 
     Ok(())
 }
+
+/// Converts a directory of Lean files in memory, without writing anything
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `tgt_dir` - Path to the target directory the Markdown files would be written to
+/// * `options` - Conversion options
+///
+/// # Returns
+///
+/// One plan per converted source file, sorted by source path, or a [`ConversionErrors`]
+/// listing every file that failed. With `options.summary` or `options.declaration_index`,
+/// a last plan whose source is `src_dir` itself holds the generated `SUMMARY.md` and
+/// declaration index.
+pub fn plan_directory(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    options: &Options,
+) -> Result<Vec<PagePlan>, Box<dyn std::error::Error>> {
+    let quizzes_dir = quizzes_dir_for(tgt_dir, options);
+
+    let sources = source_files(src_dir, options)?;
+    let index = SourceIndex::new(src_dir, &sources, tgt_dir, options)?;
+    let results = par_map(&sources, options.jobs, |(src_file, relative)| {
+        let md_path = tgt_dir.join(relative).with_extension("md");
+        plan_file(src_file, &md_path, &quizzes_dir, Some(&index), options)
+            .map_err(|e| e.to_string())
+    })?;
+
+    let mut plans = successes(&sources, results)?;
+    let book_pages = plan_book_pages(src_dir, tgt_dir, &sources, &index, options)?;
+    if !book_pages.is_empty() {
+        plans.push(PagePlan {
+            src_file: src_dir.to_path_buf(),
+            outputs: book_pages,
+        });
+    }
+
+    Ok(plans)
+}
+
+/// Generates the pages describing a whole directory build, i.e. `SUMMARY.md` and the
+/// declaration index, as far as `options` ask for them
+fn plan_book_pages(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    sources: &[(PathBuf, PathBuf)],
+    index: &SourceIndex,
+    options: &Options,
+) -> Result<Vec<OutputFile>, Box<dyn std::error::Error>> {
+    let mut pages = Vec::new();
+    if options.summary {
+        pages.push(summary::plan_summary(src_dir, tgt_dir, sources, options)?);
+    }
+    if options.declaration_index {
+        pages.push(index.declaration_page(tgt_dir));
+    }
+    Ok(pages)
+}
+
+/// The files of a directory build that could not be converted
+///
+/// Directory builds convert every file before reporting, so that all problems show up at
+/// once instead of one per run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionErrors {
+    /// Each failed source file with its error message, sorted by source path
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl std::fmt::Display for ConversionErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} file(s) failed to convert:", self.failures.len())?;
+        for (src_file, error) in &self.failures {
+            write!(f, "\n  {}: {}", src_file.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConversionErrors {}
+
+/// Applies `f` to all items on a pool of `jobs` threads (one per CPU if `None`), keeping
+/// the results in the order of `items`
+fn par_map<T: Sync, R: Send>(
+    items: &[T],
+    jobs: Option<usize>,
+    f: impl Fn(&T) -> R + Sync + Send,
+) -> Result<Vec<R>, Box<dyn std::error::Error>> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = jobs {
+        builder = builder.num_threads(jobs);
+    }
+    let pool = builder.build()?;
+    Ok(pool.install(|| items.par_iter().map(f).collect()))
+}
+
+/// Unwraps the per-file results of a directory build, or collects all failures
+fn successes<R>(
+    sources: &[(PathBuf, PathBuf)],
+    results: Vec<Result<R, String>>,
+) -> Result<Vec<R>, ConversionErrors> {
+    let mut values = Vec::new();
+    let mut failures = Vec::new();
+    for ((src_file, _), result) in sources.iter().zip(results) {
+        match result {
+            Ok(value) => values.push(value),
+            Err(e) => failures.push((src_file.clone(), e)),
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(values)
+    } else {
+        Err(ConversionErrors { failures })
+    }
+}
+
+/// The directory quizzes of a directory build are written to
+fn quizzes_dir_for(tgt_dir: &Path, options: &Options) -> PathBuf {
+    match &options.quiz_dir {
+        Some(dir) => dir.clone(),
+        None => tgt_dir.join("quizzes"),
+    }
+}
+
+/// Lists the Lean files to convert below `src_dir` together with their relative paths,
+/// leaving out files that are ignored, excluded or not included
+pub(crate) fn source_files(
+    src_dir: &Path,
+    options: &Options,
+) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn std::error::Error>> {
+    let include = glob_set(&options.include)?;
+    let exclude = glob_set(&options.exclude)?;
+    let mut files = Vec::new();
+
+    for src_file in lean_files(src_dir, !options.no_ignore)? {
+        // Mirror the source layout in the target directory
+        let relative = src_file.strip_prefix(src_dir)?.to_path_buf();
+        let included = options.include.is_empty() || matches_path(&include, &relative);
+        if !included || matches_path(&exclude, &relative) {
+            continue;
+        }
+        files.push((src_file, relative));
+    }
+
+    Ok(files)
+}
+
+/// Compiles glob patterns, in which `*` stays within a folder and `**` crosses folders
+fn glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.trim_end_matches('/'))
+            .literal_separator(true)
+            .build()?;
+        builder.add(glob);
+    }
+    builder.build()
+}
+
+/// Whether a relative path or one of the folders containing it matches a pattern
+fn matches_path(patterns: &GlobSet, relative: &Path) -> bool {
+    relative
+        .ancestors()
+        .any(|path| !path.as_os_str().is_empty() && patterns.is_match(path))
+}
+
+/// Recursively collects all `.lean` files below `dir`, sorted by path
+///
+/// With `use_ignore_files`, hidden files and folders (such as `.lake`) are skipped, as is
+/// everything ignored by a `.gitignore` or `.lean2mdignore` file in `dir`, its subfolders
+/// or its parents.
+pub(crate) fn lean_files(
+    dir: &Path,
+    use_ignore_files: bool,
+) -> Result<Vec<PathBuf>, ignore::Error> {
+    let mut walker = WalkBuilder::new(dir);
+    walker
+        .standard_filters(use_ignore_files)
+        .git_global(false)
+        .require_git(false)
+        .follow_links(true);
+    if use_ignore_files {
+        walker.add_custom_ignore_filename(IGNORE_FILE);
+    }
+
+    let mut files = Vec::new();
+    for entry in walker.build() {
+        let path = entry?.into_path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "lean") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
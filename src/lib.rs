@@ -9,7 +9,34 @@
 //! - Supports special markers for controlling output
 //! - Handles quiz generation for mdbook-quiz integration
 
+mod cache;
+mod check;
+mod config;
+mod coverage;
+mod headings;
+mod index;
+mod init;
 mod lean2md_core; // Move core functionality to this module
+mod preprocessor;
+mod quiz;
+mod summary;
+mod watch;
 
 // Export public functions for other crates to use
-pub use lean2md_core::{build_blocks, process_directory, process_file, Block};
+pub use cache::MANIFEST_FILE;
+pub use check::{check_directory, dry_run_directory};
+pub use config::{AdmonishConfig, Config, CONFIG_FILE};
+pub use coverage::{coverage_report, CoverageReport, FileCoverage};
+pub use headings::{Numbering, TOC_MARKER};
+pub use index::DECLARATIONS_FILE;
+pub use init::{init_book, LEAN_HIGHLIGHT_FILE};
+pub use lean2md_core::{
+    build_blocks, build_blocks_with_options, convert_str, plan_directory, process_directory,
+    process_directory_with_options, process_file, process_file_with_options, process_stream,
+    AdmonishStyle, Block, Conversion, ConversionErrors, NamespaceStyle, Options, OutputFile,
+    OutputKind, PagePlan, IGNORE_FILE,
+};
+pub use preprocessor::{run_preprocessor, supports_renderer, PREPROCESSOR_NAME};
+pub use quiz::{collect_questions, export_question_bank, BankQuestion, Quiz, QuizMode};
+pub use summary::{ORDER_FILE, SUMMARY_FILE};
+pub use watch::{watch_directory, WatchSettings};
@@ -1,42 +1,537 @@
-use lean2md::{process_directory, process_file};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use lean2md::{
+    check_directory, coverage_report, dry_run_directory, export_question_bank, init_book,
+    process_directory_with_options, process_file_with_options, process_stream, run_preprocessor,
+    supports_renderer, watch_directory, Config, NamespaceStyle, Numbering, Options, QuizMode,
+    WatchSettings,
+};
 use std::env;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+/// Convert Lean files to Markdown with special features for documentation
+#[derive(Parser)]
+// Keep the `lean2md version X` output scripts already rely on
+#[command(
+    name = "lean2md",
+    version = concat!("version ", env!("CARGO_PKG_VERSION")),
+    about
+)]
+struct Cli {
+    /// Configuration file to use instead of searching for lean2md.toml or lakefile.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
 
-    if args.len() == 2 {
-        if args[1] == "--version" {
-            let version = env!("CARGO_PKG_VERSION");
-            println!("lean2md version {}", version);
-            return Ok(());
-        } else {
-            // Case: lean2md <file.lean>
-            let src = PathBuf::from(&args[1]);
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a single Lean file to Markdown
+    Convert {
+        /// The Lean file to convert, or `-` to read from standard input
+        #[arg(required_unless_present = "stdin")]
+        src: Option<PathBuf>,
+        /// The Markdown file to write (defaults to the source with an .md extension,
+        /// or standard output when reading from standard input)
+        tgt: Option<PathBuf>,
+        /// Read the Lean source from standard input (same as `-`); a single path is then
+        /// the Markdown file to write
+        #[arg(long)]
+        stdin: bool,
+        #[command(flatten)]
+        convert: ConvertArgs,
+    },
+    /// Convert all Lean files in a directory tree to Markdown
+    Build {
+        /// The directory containing the Lean sources (defaults to `src` from the configuration)
+        src: Option<PathBuf>,
+        /// The directory the Markdown files are written to (defaults to `tgt` from the configuration)
+        tgt: Option<PathBuf>,
+        /// Only report the files and directories that would be written
+        #[arg(long)]
+        dry_run: bool,
+        /// Reconvert all files, even those the build cache considers unchanged
+        #[arg(long)]
+        force: bool,
+        /// Delete previously generated files whose source or quiz no longer exists
+        #[arg(long)]
+        prune: bool,
+        /// Write the files that convert even if others fail, then summarise the failures
+        #[arg(long)]
+        keep_going: bool,
+        /// Number of files converted in parallel [default: one per CPU]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        #[command(flatten)]
+        convert: ConvertArgs,
+    },
+    /// Check that the Markdown in a target directory is up to date, without writing anything
+    Check {
+        /// The directory containing the Lean sources (defaults to `src` from the configuration)
+        src: Option<PathBuf>,
+        /// The directory holding the generated Markdown (defaults to `tgt` from the configuration)
+        tgt: Option<PathBuf>,
+        /// Number of files converted in parallel [default: one per CPU]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        #[command(flatten)]
+        convert: ConvertArgs,
+    },
+    /// Report which declarations lack docstrings and how much prose each file has
+    Coverage {
+        /// The directory containing the Lean sources (defaults to `src` from the configuration)
+        src: Option<PathBuf>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        /// Exit with an error if less than this percentage of declarations is documented
+        #[arg(long, value_name = "PERCENT")]
+        min_coverage: Option<f64>,
+        #[command(flatten)]
+        sources: SourceArgs,
+    },
+    /// Convert a directory and reconvert changed Lean files until interrupted
+    Watch {
+        /// The directory containing the Lean sources (defaults to `src` from the configuration)
+        src: Option<PathBuf>,
+        /// The directory the Markdown files are written to (defaults to `tgt` from the configuration)
+        tgt: Option<PathBuf>,
+        /// Poll the filesystem instead of using native change notifications
+        #[arg(long)]
+        poll: bool,
+        /// Milliseconds without changes before changed files are reconverted
+        #[arg(long, value_name = "MS", default_value_t = 200)]
+        debounce: u64,
+        /// Number of files converted in parallel [default: one per CPU]
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        #[command(flatten)]
+        convert: ConvertArgs,
+    },
+    /// Create an mdbook project for a directory of Lean files and run the first conversion
+    Init {
+        /// The directory containing the Lean sources
+        lean_dir: PathBuf,
+        /// The directory the book is created in
+        book_dir: PathBuf,
+    },
+    /// Run as an mdbook preprocessor, converting `.lean` chapters and `{{#lean2md}}` includes
+    MdbookPreprocessor {
+        #[command(subcommand)]
+        command: Option<PreprocessorCommand>,
+    },
+    /// Export all quizzes of a source tree as a question bank and answer key
+    QuizBank {
+        /// The directory containing the Lean sources
+        src: PathBuf,
+        /// The directory the question bank files are written to
+        out: PathBuf,
+        #[command(flatten)]
+        sources: SourceArgs,
+    },
+    /// Print a shell completion script to standard output
+    Completions {
+        /// The shell to generate completions for
+        shell: Shell,
+    },
+}
 
-            if src.is_file() && src.extension().is_some_and(|ext| ext == "lean") {
-                let tgt = src.with_extension("md");
-                return process_file(&src, &tgt);
-            }
+#[derive(Subcommand)]
+enum PreprocessorCommand {
+    /// Exit successfully if the given mdbook renderer is supported
+    Supports {
+        /// The name of the renderer
+        renderer: String,
+    },
+}
+
+/// Options shared by all commands that convert Lean files
+#[derive(Args)]
+struct ConvertArgs {
+    /// How quizzes are emitted: file, fenced or html [default: file]
+    #[arg(long, value_name = "MODE")]
+    quiz_mode: Option<QuizMode>,
+    /// Directory quiz files are written to (defaults to `quizzes` in the target directory)
+    #[arg(long, value_name = "DIR")]
+    quiz_dir: Option<PathBuf>,
+    /// Directory the Lean answer check files are written to (defaults to the quiz directory)
+    #[arg(long, value_name = "DIR")]
+    lean_check_dir: Option<PathBuf>,
+    /// Admonition kind used for docstring admonish blocks [default: abstract]
+    #[arg(long, value_name = "KIND")]
+    admonish_kind: Option<String>,
+    /// Title of docstring admonish blocks [default: Docstring]
+    #[arg(long, value_name = "TITLE")]
+    admonish_title: Option<String>,
+    /// Make docstring admonish blocks collapsible (`--admonish-collapsible=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    admonish_collapsible: Option<bool>,
+    /// How namespaces are shown: off, headings or breadcrumbs [default: off]
+    #[arg(long, value_name = "STYLE")]
+    namespaces: Option<NamespaceStyle>,
+    /// Add a table of contents to every page, not only where a --@toc marker is (`--toc=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    toc: Option<bool>,
+    /// Move every heading in the prose down by this many levels
+    #[arg(long, value_name = "N")]
+    heading_shift: Option<usize>,
+    /// Number the headings in the prose: off, file or book [default: off]
+    #[arg(long, value_name = "SCOPE")]
+    numbering: Option<Numbering>,
+    #[command(flatten)]
+    sources: SourceArgs,
+    /// File whose content is inserted at the top of every generated page
+    #[arg(long, value_name = "FILE")]
+    front_matter: Option<PathBuf>,
+    /// Generate an mdbook SUMMARY.md for directory builds (`--summary=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    summary: Option<bool>,
+    /// Start each page with links to the pages of the modules it imports (`--import-links=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    import_links: Option<bool>,
+    /// Link inline code naming a declaration to its definition (`--auto-link=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    auto_link: Option<bool>,
+    /// Generate declarations.md listing all declarations (`--declaration-index=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    declaration_index: Option<bool>,
+}
+
+/// Options selecting the Lean files read below the source directory
+#[derive(Args)]
+struct SourceArgs {
+    /// Only read files matching this glob (relative to the source directory); can be repeated
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Skip files and folders matching this glob (relative to the source directory); can be repeated
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Also read files ignored by .gitignore or .lean2mdignore files and hidden folders (`--no-ignore=false` to undo)
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    no_ignore: Option<bool>,
+}
+
+impl SourceArgs {
+    /// Builds the options from the configuration, overridden by the source selection flags
+    fn options(self, config: Option<&Config>) -> Result<Options, Box<dyn std::error::Error>> {
+        let mut options = Options::default();
+        if let Some(config) = config {
+            config.apply(&mut options)?;
+        }
+
+        options.include.extend(self.include);
+        options.exclude.extend(self.exclude);
+        if let Some(no_ignore) = self.no_ignore {
+            options.no_ignore = no_ignore;
         }
-    } else if args.len() == 3 {
-        let src = PathBuf::from(&args[1]);
-        let tgt = PathBuf::from(&args[2]);
 
-        if src.is_file() {
-            // Case: lean2md <lean_src_file> <md_tgt_file>
-            return process_file(&src, &tgt);
-        } else if src.is_dir() {
-            // Case: lean2md <lean_src_dir> <md_tgt_dir>
-            return process_directory(&src, &tgt);
+        Ok(options)
+    }
+}
+
+impl ConvertArgs {
+    /// Builds the conversion options from the configuration, overridden by the flags
+    fn options(self, config: Option<&Config>) -> Result<Options, Box<dyn std::error::Error>> {
+        let mut options = self.sources.options(config)?;
+
+        if let Some(mode) = self.quiz_mode {
+            options.quiz_mode = mode;
         }
+        if let Some(dir) = self.quiz_dir {
+            options.quiz_dir = Some(dir);
+        }
+        if let Some(dir) = self.lean_check_dir {
+            options.lean_check_dir = Some(dir);
+        }
+        if let Some(kind) = self.admonish_kind {
+            options.admonish.kind = kind;
+        }
+        if let Some(title) = self.admonish_title {
+            options.admonish.title = title;
+        }
+        if let Some(collapsible) = self.admonish_collapsible {
+            options.admonish.collapsible = collapsible;
+        }
+        if let Some(style) = self.namespaces {
+            options.namespaces = style;
+        }
+        if let Some(toc) = self.toc {
+            options.toc = toc;
+        }
+        if let Some(shift) = self.heading_shift {
+            options.heading_shift = shift;
+        }
+        if let Some(numbering) = self.numbering {
+            options.numbering = numbering;
+        }
+        if let Some(path) = self.front_matter {
+            options.front_matter = Some(fs::read_to_string(path)?);
+        }
+        if let Some(summary) = self.summary {
+            options.summary = summary;
+        }
+        if let Some(import_links) = self.import_links {
+            options.import_links = import_links;
+        }
+        if let Some(auto_link) = self.auto_link {
+            options.auto_link = auto_link;
+        }
+        if let Some(declaration_index) = self.declaration_index {
+            options.declaration_index = declaration_index;
+        }
+
+        Ok(options)
     }
+}
+
+fn main() {
+    // Print errors with their message rather than their debug representation, which
+    // matters for the multi-line list of files that failed to convert
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse_from(with_default_command(env::args().collect()));
+    let config = match &cli.config {
+        Some(path) => Some(Config::load(path)?),
+        None => Config::discover(&env::current_dir()?)?,
+    };
+
+    match cli.command {
+        Command::Convert {
+            src,
+            tgt,
+            stdin,
+            convert,
+        } => {
+            let (src, tgt) = match (stdin, src, tgt) {
+                (true, _, Some(_)) => return Err("--stdin takes at most one path".into()),
+                (true, tgt, None) => (None, tgt),
+                (false, src, tgt) => (src, tgt),
+            };
+            let options = convert.options(config.as_ref())?;
+            match src {
+                Some(src) if !is_stdio(&src) => {
+                    let tgt = tgt.unwrap_or_else(|| src.with_extension("md"));
+                    process_file_with_options(&src, &tgt, &options)
+                }
+                _ => match tgt {
+                    Some(tgt) if !is_stdio(&tgt) => {
+                        // Only create the target once the conversion has succeeded
+                        let mut markdown = Vec::new();
+                        process_stream(&mut io::stdin(), &mut markdown, &options)?;
+                        fs::write(tgt, markdown)?;
+                        Ok(())
+                    }
+                    _ => process_stream(&mut io::stdin(), &mut io::stdout(), &options),
+                },
+            }
+        }
+        Command::Build {
+            src,
+            tgt,
+            dry_run,
+            force,
+            prune,
+            keep_going,
+            jobs,
+            convert,
+        } => {
+            let (src, tgt) = directories(src, tgt, config.as_ref())?;
+            let options = convert.options(config.as_ref())?;
+            let options = Options {
+                force,
+                prune,
+                keep_going,
+                jobs: jobs.or(options.jobs),
+                ..options
+            };
+            if dry_run {
+                dry_run_directory(&src, &tgt, &options, &mut io::stdout())
+            } else {
+                process_directory_with_options(&src, &tgt, &options)
+            }
+        }
+        Command::Check {
+            src,
+            tgt,
+            jobs,
+            convert,
+        } => {
+            let (src, tgt) = directories(src, tgt, config.as_ref())?;
+            let options = convert.options(config.as_ref())?;
+            let options = Options {
+                jobs: jobs.or(options.jobs),
+                ..options
+            };
+            if !check_directory(&src, &tgt, &options, &mut io::stdout())? {
+                process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Coverage {
+            src,
+            json,
+            min_coverage,
+            sources,
+        } => {
+            let src = source_directory(src, config.as_ref())?;
+            let options = sources.options(config.as_ref())?;
+            let report = coverage_report(&src, &options)?;
+            if json {
+                report.write_json(&mut io::stdout())?;
+            } else {
+                report.write_text(&mut io::stdout())?;
+            }
+            if let Some(min_coverage) = min_coverage {
+                if report.coverage < min_coverage {
+                    eprintln!(
+                        "Documentation coverage {:.1}% is below the minimum of {}%",
+                        report.coverage, min_coverage
+                    );
+                    process::exit(1);
+                }
+            }
+            Ok(())
+        }
+        Command::Watch {
+            src,
+            tgt,
+            poll,
+            debounce,
+            jobs,
+            convert,
+        } => {
+            let (src, tgt) = directories(src, tgt, config.as_ref())?;
+            let options = convert.options(config.as_ref())?;
+            let options = Options {
+                jobs: jobs.or(options.jobs),
+                ..options
+            };
+            let settings = WatchSettings {
+                debounce: Duration::from_millis(debounce),
+                poll,
+                ..Default::default()
+            };
+            watch_directory(&src, &tgt, &options, &settings)
+        }
+        Command::Init { lean_dir, book_dir } => init_book(&lean_dir, &book_dir),
+        Command::MdbookPreprocessor { command } => match command {
+            Some(PreprocessorCommand::Supports { renderer }) => {
+                if !supports_renderer(&renderer) {
+                    process::exit(1);
+                }
+                Ok(())
+            }
+            None => run_preprocessor(&mut io::stdin(), &mut io::stdout()),
+        },
+        Command::QuizBank { src, out, sources } => {
+            export_question_bank(&src, &out, &sources.options(config.as_ref())?)
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "lean2md", &mut io::stdout());
+            Ok(())
+        }
+    }
+}
+
+/// Resolves the source and target directories from the arguments or the configuration
+fn directories(
+    src: Option<PathBuf>,
+    tgt: Option<PathBuf>,
+    config: Option<&Config>,
+) -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    let src = source_directory(src, config)?;
+    let tgt = tgt
+        .or_else(|| config.and_then(|c| c.tgt.as_ref().map(|p| c.resolve(p))))
+        .ok_or("No target directory given and none configured")?;
+    Ok((src, tgt))
+}
+
+/// Resolves the source directory from the argument or the configuration
+fn source_directory(
+    src: Option<PathBuf>,
+    config: Option<&Config>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(src
+        .or_else(|| config.and_then(|c| c.src.as_ref().map(|p| c.resolve(p))))
+        .ok_or("No source directory given and none configured")?)
+}
+
+/// Keeps the original `lean2md <src> [tgt]` invocation working by inserting the
+/// matching subcommand when the first argument is a path rather than a command
+fn with_default_command(mut args: Vec<String>) -> Vec<String> {
+    let Some(first) = args.get(1) else {
+        return args;
+    };
+    let is_command = Cli::command()
+        .get_subcommands()
+        .any(|command| command.get_name() == first)
+        || first == "help"
+        || (first.starts_with('-') && first != "-" && first != "--stdin");
+
+    if !is_command {
+        let command = if PathBuf::from(first).is_dir() {
+            "build"
+        } else {
+            "convert"
+        };
+        args.insert(1, command.to_string());
+    }
+    args
+}
 
-    // If we reach here, arguments were invalid
-    println!("Usage:");
-    println!("  lean2md <file.lean>                  # Convert to <file.md>");
-    println!("  lean2md <lean_src_file> <md_tgt_file>   # Convert file to file");
-    println!("  lean2md <lean_src_dir> <md_tgt_dir>     # Convert directory to directory");
-    println!("  lean2md --version                    # Display version information");
-    Ok(())
+/// Whether a path argument stands for standard input or output
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
 }
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::lean2md_core::{convert_str, relative_link, write_outputs, Options};
+
+/// Name of the preprocessor in `book.toml` (`[preprocessor.lean2md]`)
+pub const PREPROCESSOR_NAME: &str = "lean2md";
+
+/// Keys mdbook itself reads from the preprocessor table, which lean2md ignores
+const MDBOOK_KEYS: [&str; 5] = ["command", "before", "after", "renderers", "optional"];
+
+/// Whether the preprocessor supports the given mdbook renderer
+///
+/// The output is plain Markdown, so every renderer is supported.
+pub fn supports_renderer(_renderer: &str) -> bool {
+    true
+}
+
+/// Runs lean2md as an mdbook preprocessor
+///
+/// Reads the `[context, book]` JSON mdbook writes to a preprocessor from `input` and
+/// writes the processed book to `output`. Chapters whose source is a `.lean` file are
+/// converted to Markdown, and `{{#lean2md path/File.lean}}` in other chapters is replaced
+/// by the converted file (the path being relative to the chapter, as for
+/// `{{#include}}`).
+///
+/// Settings are read from `[preprocessor.lean2md]`, which accepts the same keys as
+/// `lean2md.toml` with paths relative to the book root. Quiz files are written to the
+/// `quizzes` folder of the book's source directory unless configured otherwise.
+///
+/// # Arguments
+///
+/// * `input` - Reader providing mdbook's JSON
+/// * `output` - Writer receiving the processed book as JSON
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn run_preprocessor(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let [context, mut book]: [Value; 2] = serde_json::from_reader(input)?;
+
+    let root = PathBuf::from(context["root"].as_str().unwrap_or("."));
+    let src_dir = root.join(context["config"]["book"]["src"].as_str().unwrap_or("src"));
+
+    let mut table = context["config"]["preprocessor"][PREPROCESSOR_NAME].clone();
+    if let Some(table) = table.as_object_mut() {
+        for key in MDBOOK_KEYS {
+            table.remove(key);
+        }
+    }
+    let mut config: Config = match table {
+        Value::Null => Config::default(),
+        table => serde_json::from_value(table)
+            .map_err(|e| format!("[preprocessor.{}]: {}", PREPROCESSOR_NAME, e))?,
+    };
+    config.base_dir = root;
+
+    let mut options = Options::default();
+    config.apply(&mut options)?;
+    let book_dirs = BookDirs {
+        quizzes_dir: options
+            .quiz_dir
+            .clone()
+            .unwrap_or_else(|| src_dir.join("quizzes")),
+        src_dir,
+    };
+
+    if let Some(sections) = book.get_mut("sections").and_then(Value::as_array_mut) {
+        process_items(sections, &book_dirs, &options)?;
+    }
+
+    serde_json::to_writer(&mut *output, &book)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Where the book's sources and generated quizzes live
+struct BookDirs {
+    src_dir: PathBuf,
+    quizzes_dir: PathBuf,
+}
+
+fn process_items(
+    items: &mut [Value],
+    dirs: &BookDirs,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for item in items {
+        let Some(chapter) = item.get_mut("Chapter") else {
+            continue;
+        };
+
+        if let Some(path) = chapter["source_path"].as_str().map(PathBuf::from) {
+            let src_file = dirs.src_dir.join(&path);
+            let content = chapter["content"].as_str().unwrap_or_default();
+            let converted = if path.extension().is_some_and(|ext| ext == "lean") {
+                convert_page(content, &src_file, dirs, options)
+            } else {
+                expand_includes(content, &src_file, dirs, options)
+            };
+            let converted = converted.map_err(|e| format!("{}: {}", path.display(), e))?;
+            chapter["content"] = Value::String(converted);
+        }
+
+        if let Some(sub_items) = chapter.get_mut("sub_items").and_then(Value::as_array_mut) {
+            process_items(sub_items, dirs, options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts Lean source shown on the page of `chapter_file`, writing its quiz files
+fn convert_page(
+    content: &str,
+    chapter_file: &Path,
+    dirs: &BookDirs,
+    options: &Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Quiz references are relative to the chapter that contains them
+    let page_dir = chapter_file.parent().unwrap_or(Path::new(""));
+    let mut quiz_link_dir = relative_link(page_dir, &dirs.quizzes_dir);
+    if !quiz_link_dir.is_empty() {
+        quiz_link_dir.push('/');
+    }
+
+    let conversion = convert_str(content, &quiz_link_dir, options)?;
+    let markdown = conversion.markdown.clone();
+
+    // Standard output carries the book, so messages go to standard error
+    let outputs = conversion.into_outputs(None, &dirs.quizzes_dir, options);
+    write_outputs(&outputs, None, &mut std::io::stderr())?;
+
+    Ok(markdown)
+}
+
+/// Replaces every `{{#lean2md path}}` in a Markdown chapter by the converted Lean file
+fn expand_includes(
+    content: &str,
+    chapter_file: &Path,
+    dirs: &BookDirs,
+    options: &Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    const START: &str = "{{#lean2md ";
+
+    // Front matter belongs at the top of a page, not in the middle of one
+    let options = Options {
+        front_matter: None,
+        ..options.clone()
+    };
+
+    let mut result = String::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(START) {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let path = rest[start + START.len()..start + len].trim();
+        let lean_file = chapter_file.parent().unwrap_or(Path::new("")).join(path);
+        let lean = fs::read_to_string(&lean_file)
+            .map_err(|e| format!("{}: {}", lean_file.display(), e))?;
+        let markdown = convert_page(&lean, chapter_file, dirs, &options)
+            .map_err(|e| format!("{}: {}", lean_file.display(), e))?;
+
+        result.push_str(&rest[..start]);
+        result.push_str(markdown.trim_end());
+        rest = &rest[start + len + 2..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
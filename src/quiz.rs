@@ -0,0 +1,415 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lean2md_core::{build_blocks_with_options, source_files, Options};
+
+/// A quiz extracted from a `--@quiz:name` ... `--@quiz-end` block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quiz {
+    /// The quiz name given after `--@quiz:`
+    pub name: String,
+    /// The quiz definition in mdbook-quiz TOML syntax
+    pub content: String,
+    /// The 1-based line of the `--@quiz:` marker in the source file
+    pub line: usize,
+}
+
+/// Determines how quizzes from `--@quiz:` blocks end up in the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuizMode {
+    /// Write each quiz to a TOML file and reference it with `{{#quiz ...}}` (mdbook-quiz)
+    #[default]
+    File,
+    /// Embed the quiz definition verbatim in a fenced ```` ```quiz ```` block
+    Fenced,
+    /// Embed an HTML `<div>` carrying the questions as JSON
+    Html,
+}
+
+impl FromStr for QuizMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(QuizMode::File),
+            "fenced" => Ok(QuizMode::Fenced),
+            "html" => Ok(QuizMode::Html),
+            _ => Err(format!(
+                "Unknown quiz mode '{}' (expected 'file', 'fenced' or 'html')",
+                s
+            )),
+        }
+    }
+}
+
+/// Renders a quiz directly into the Markdown output
+///
+/// Must only be called for the inline modes; `QuizMode::File` is handled by the caller,
+/// which writes the TOML file and emits a `{{#quiz ...}}` reference instead.
+pub fn render_inline(quiz: &Quiz, mode: QuizMode) -> Result<String, String> {
+    match mode {
+        QuizMode::Fenced => Ok(format!("```quiz\n{}\n```", quiz.content.trim_end())),
+        QuizMode::Html => {
+            let questions = parse(quiz)?;
+            let json = serde_json::to_string(&questions)
+                .map_err(|e| format!("Cannot serialise quiz '{}': {}", quiz.name, e))?;
+            Ok(format!(
+                "<div class=\"quiz-placeholder\" data-quiz-name=\"{}\" data-quiz-questions=\"{}\"></div>",
+                escape_html_attribute(&quiz.name),
+                escape_html_attribute(&json)
+            ))
+        }
+        QuizMode::File => Err(format!("Quiz '{}' is not rendered inline", quiz.name)),
+    }
+}
+
+fn parse(quiz: &Quiz) -> Result<toml::Table, String> {
+    quiz.content
+        .parse::<toml::Table>()
+        .map_err(|e| format!("Invalid quiz '{}' at line {}: {}", quiz.name, quiz.line, e))
+}
+
+/// Rewrites `type = "Lean"` questions into mdbook-quiz `ShortAnswer` questions
+///
+/// A Lean question carries the Lean code the answer completes in `prompt.lean`; it is shown
+/// below the prompt as a code block. The companion check file contains, for every Lean
+/// question, that code followed by the answer so that compiling it proves the answer key
+/// is correct. Imports needed by the check file can be listed in a top-level `lean_imports`
+/// array.
+///
+/// Returns the rewritten quiz and the check file content, or the unchanged quiz and `None`
+/// if it has no Lean questions.
+pub fn expand_lean_questions(quiz: &Quiz) -> Result<(Quiz, Option<String>), String> {
+    // Quizzes without Lean questions are copied verbatim, without being parsed
+    if !mentions_lean_type(&quiz.content) {
+        return Ok((quiz.clone(), None));
+    }
+
+    let table = parse(quiz)?;
+    let entries = match table.get("questions") {
+        Some(toml::Value::Array(entries)) if entries.iter().any(is_lean_question) => entries,
+        _ => return Ok((quiz.clone(), None)),
+    };
+
+    let mut check = format!(
+        "-- Answer checks for quiz `{}`, generated by lean2md. Do not edit.\n",
+        quiz.name
+    );
+    if let Some(imports) = table.get("lean_imports") {
+        let imports = imports
+            .as_array()
+            .ok_or_else(|| format!("Quiz '{}': lean_imports must be an array", quiz.name))?;
+        for import in imports {
+            check.push_str(&format!("import {}\n", display_value(import)));
+        }
+    }
+
+    let lines = question_lines(quiz);
+    let mut prompts = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        if !is_lean_question(entry) {
+            prompts.push(None);
+            continue;
+        }
+        let line = lines.get(idx).copied().unwrap_or(quiz.line);
+        let code = string_at(entry, &["prompt", "lean"]).unwrap_or_default();
+        let answer = string_at(entry, &["answer", "answer"]).ok_or_else(|| {
+            format!(
+                "Quiz '{}': Lean question at line {} has no answer.answer",
+                quiz.name, line
+            )
+        })?;
+
+        check.push_str(&format!(
+            "\n-- Question {} (line {})\nsection\n",
+            idx + 1,
+            line
+        ));
+        if !code.trim().is_empty() {
+            check.push_str(code.trim_end());
+            check.push('\n');
+        }
+        for answer_line in answer.trim().lines() {
+            check.push_str(&format!("  {}\n", answer_line));
+        }
+        check.push_str("end\n");
+
+        prompts.push(Some(lean_prompt(entry)));
+    }
+
+    // Only the Lean questions are edited, keeping the comments and layout of the rest
+    let mut document = quiz
+        .content
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| format!("Invalid quiz '{}' at line {}: {}", quiz.name, quiz.line, e))?;
+    document.remove("lean_imports");
+    let questions: Vec<&mut dyn toml_edit::TableLike> = match document.get_mut("questions") {
+        Some(toml_edit::Item::ArrayOfTables(tables)) => tables
+            .iter_mut()
+            .map(|table| table as &mut dyn toml_edit::TableLike)
+            .collect(),
+        Some(toml_edit::Item::Value(toml_edit::Value::Array(values))) => values
+            .iter_mut()
+            .filter_map(|value| value.as_inline_table_mut())
+            .map(|table| table as &mut dyn toml_edit::TableLike)
+            .collect(),
+        _ => Vec::new(),
+    };
+    for (question, prompt) in questions.into_iter().zip(prompts) {
+        let Some(prompt) = prompt else {
+            continue;
+        };
+        set_string(question, "type", "ShortAnswer");
+        if let Some(prompt_table) = question
+            .get_mut("prompt")
+            .and_then(|item| item.as_table_like_mut())
+        {
+            prompt_table.remove("lean");
+            set_string(prompt_table, "prompt", &prompt);
+        }
+    }
+
+    let expanded = Quiz {
+        content: document.to_string().trim_start().to_string(),
+        ..quiz.clone()
+    };
+    Ok((expanded, Some(check)))
+}
+
+/// Sets a string value in a TOML table, keeping the comments around an existing value
+fn set_string(table: &mut dyn toml_edit::TableLike, key: &str, value: &str) {
+    match table.get_mut(key).and_then(|item| item.as_value_mut()) {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = value.into();
+            *existing.decor_mut() = decor;
+        }
+        None => {
+            table.insert(key, toml_edit::value(value));
+        }
+    }
+}
+
+/// Whether a quiz may contain a `type = "Lean"` question, checked without parsing it
+fn mentions_lean_type(content: &str) -> bool {
+    content.lines().any(|line| {
+        let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+        line.contains("type=\"Lean\"") || line.contains("type='Lean'")
+    })
+}
+
+fn is_lean_question(entry: &toml::Value) -> bool {
+    string_at(entry, &["type"]).as_deref() == Some("Lean")
+}
+
+/// The prompt of a question, followed by its Lean code (if any) as a code block
+fn lean_prompt(entry: &toml::Value) -> String {
+    let prompt = string_at(entry, &["prompt", "prompt"]).unwrap_or_default();
+    match string_at(entry, &["prompt", "lean"]) {
+        Some(code) if !code.trim().is_empty() => {
+            format!("{}\n\n```lean\n{}\n```", prompt.trim_end(), code.trim_end())
+        }
+        _ => prompt,
+    }
+}
+
+fn escape_html_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A single question collected into the question bank
+#[derive(Debug, Clone, Serialize)]
+pub struct BankQuestion {
+    /// Name of the quiz the question belongs to
+    pub quiz: String,
+    /// Source file, relative to the scanned directory
+    pub source: String,
+    /// 1-based line of the question's `[[questions]]` header in the source file
+    pub line: usize,
+    /// The mdbook-quiz question type (`ShortAnswer`, `MultipleChoice`, ...)
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The question prompt (or the program, for `Tracing` questions)
+    pub prompt: String,
+    /// The correct answer(s)
+    pub answers: Vec<String>,
+    /// Incorrect options offered for multiple choice questions
+    pub distractors: Vec<String>,
+    /// Explanation shown after answering, if any
+    pub context: Option<String>,
+}
+
+/// Collects every quiz question found below `src_dir`
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `options` - Conversion options; their include, exclude and ignore settings select the
+///   files read, as in a directory build
+///
+/// # Returns
+///
+/// The questions in source order, or an error if a file or quiz cannot be parsed
+pub fn collect_questions(
+    src_dir: &Path,
+    options: &Options,
+) -> Result<Vec<BankQuestion>, Box<dyn std::error::Error>> {
+    let mut questions = Vec::new();
+
+    for (src_file, relative) in source_files(src_dir, options)? {
+        let content = fs::read_to_string(&src_file)?;
+        let (_, quizzes) = build_blocks_with_options(&content, options)
+            .map_err(|e| format!("{}: {}", src_file.display(), e))?;
+        let source = relative.to_string_lossy().replace('\\', "/");
+
+        for quiz in &quizzes {
+            let table = parse(quiz).map_err(|e| format!("{}: {}", source, e))?;
+            let entries = match table.get("questions") {
+                Some(toml::Value::Array(entries)) => entries.clone(),
+                _ => Vec::new(),
+            };
+            let lines = question_lines(quiz);
+
+            for (idx, entry) in entries.iter().enumerate() {
+                questions.push(BankQuestion {
+                    quiz: quiz.name.clone(),
+                    source: source.clone(),
+                    line: lines.get(idx).copied().unwrap_or(quiz.line),
+                    kind: string_at(entry, &["type"]).unwrap_or_default(),
+                    prompt: string_at(entry, &["prompt", "program"])
+                        .unwrap_or_else(|| lean_prompt(entry)),
+                    answers: answers(entry),
+                    distractors: strings_at(entry, &["prompt", "distractors"]),
+                    context: string_at(entry, &["context"]),
+                });
+            }
+        }
+    }
+
+    Ok(questions)
+}
+
+/// Writes `question_bank.json` and `answer_key.md` for all quizzes below `src_dir`
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `out_dir` - Directory where the question bank files are written
+/// * `options` - Conversion options selecting the files read
+///
+/// # Returns
+///
+/// Result containing `()` on success or an error message on failure
+pub fn export_question_bank(
+    src_dir: &Path,
+    out_dir: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let questions = collect_questions(src_dir, options)?;
+
+    fs::create_dir_all(out_dir)?;
+
+    let json_path = out_dir.join("question_bank.json");
+    fs::write(&json_path, serde_json::to_string_pretty(&questions)? + "\n")?;
+    println!("Generated question bank: {}", json_path.display());
+
+    let key_path = out_dir.join("answer_key.md");
+    fs::write(&key_path, answer_key(&questions))?;
+    println!("Generated answer key: {}", key_path.display());
+
+    Ok(())
+}
+
+/// Renders the questions as a Markdown answer key, grouped by quiz
+fn answer_key(questions: &[BankQuestion]) -> String {
+    let mut result = String::from("# Answer Key\n");
+    let mut current_quiz: Option<(&str, &str)> = None;
+    let mut number = 0;
+
+    for question in questions {
+        if current_quiz != Some((&question.quiz, &question.source)) {
+            current_quiz = Some((&question.quiz, &question.source));
+            number = 0;
+            result.push_str(&format!(
+                "\n## {}\n\nSource: `{}`\n",
+                question.quiz, question.source
+            ));
+        }
+        number += 1;
+
+        result.push_str(&format!(
+            "\n### Question {} ({}, line {})\n\n",
+            number, question.kind, question.line
+        ));
+        result.push_str(&format!("**Prompt:** {}\n\n", question.prompt.trim()));
+        result.push_str(&format!("**Answer:** {}\n", question.answers.join(", ")));
+        if !question.distractors.is_empty() {
+            result.push_str(&format!(
+                "\n**Distractors:** {}\n",
+                question.distractors.join(", ")
+            ));
+        }
+        if let Some(context) = &question.context {
+            result.push_str(&format!("\n**Context:** {}\n", context.trim()));
+        }
+    }
+
+    result
+}
+
+/// Finds the source line of each `[[questions]]` header of a quiz
+fn question_lines(quiz: &Quiz) -> Vec<usize> {
+    quiz.content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim() == "[[questions]]")
+        // The first content line directly follows the `--@quiz:` marker
+        .map(|(idx, _)| quiz.line + idx + 1)
+        .collect()
+}
+
+fn value_at<'a>(value: &'a toml::Value, path: &[&str]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+fn string_at(value: &toml::Value, path: &[&str]) -> Option<String> {
+    value_at(value, path)
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+}
+
+fn strings_at(value: &toml::Value, path: &[&str]) -> Vec<String> {
+    match value_at(value, path) {
+        Some(toml::Value::Array(items)) => items.iter().map(display_value).collect(),
+        Some(item) => vec![display_value(item)],
+        None => Vec::new(),
+    }
+}
+
+fn answers(entry: &toml::Value) -> Vec<String> {
+    // Tracing questions describe the expected compiler behaviour instead of an answer
+    if let Some(does_compile) = value_at(entry, &["answer", "doesCompile"]) {
+        let mut answers = vec![format!("doesCompile = {}", display_value(does_compile))];
+        if let Some(stdout) = string_at(entry, &["answer", "stdout"]) {
+            answers.push(format!("stdout = {}", stdout));
+        }
+        return answers;
+    }
+
+    strings_at(entry, &["answer", "answer"])
+}
+
+fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
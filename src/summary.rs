@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::index::DECLARATIONS_FILE;
+use crate::lean2md_core::{build_blocks, relative_link, Options, OutputFile, OutputKind};
+
+/// Name of the mdbook table of contents generated in the target directory
+pub const SUMMARY_FILE: &str = "SUMMARY.md";
+
+/// Name of the optional file in a source folder listing its entries in chapter order
+pub const ORDER_FILE: &str = ".lean2md-order";
+
+/// A file or folder of the source tree, keyed by name without extension
+#[derive(Default)]
+struct Entry {
+    /// Source and target of the page, if there is a Lean file with this name
+    page: Option<(PathBuf, PathBuf)>,
+    /// Pages and folders inside the folder with this name
+    children: BTreeMap<String, Entry>,
+}
+
+/// Builds an mdbook `SUMMARY.md` for the converted sources of a directory build
+///
+/// Folders become chapters whose sub-chapters are the files inside. A Lean file with the
+/// same name as a folder next to it (`Chapter.lean` and `Chapter/`) is used as the page of
+/// that chapter; otherwise the chapter is a draft without a page. Chapter names are the
+/// first Markdown heading of a page, falling back to the file name.
+///
+/// Entries listed in a folder's `.lean2md-order` file come first, in the order given.
+/// The remaining ones are sorted by numeric prefix (`01_Intro.lean`) and then by name.
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `tgt_dir` - Path to the target directory the pages are written to
+/// * `sources` - The converted source files with their paths relative to `src_dir`
+/// * `options` - Conversion options; the declaration index is listed last if generated
+pub(crate) fn plan_summary(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    sources: &[(PathBuf, PathBuf)],
+    options: &Options,
+) -> Result<OutputFile, Box<dyn std::error::Error>> {
+    let root = source_tree(tgt_dir, sources);
+
+    let mut content = String::from("# Summary\n\n");
+    render_entries(&root, src_dir, tgt_dir, 0, &mut content)?;
+    if options.declaration_index {
+        content.push_str(&format!("- [Declaration Index]({})\n", DECLARATIONS_FILE));
+    }
+
+    Ok(OutputFile {
+        path: tgt_dir.join(SUMMARY_FILE),
+        content,
+        kind: OutputKind::Summary,
+    })
+}
+
+/// The sources of a directory build with their pages, in the order of `SUMMARY.md`
+pub(crate) fn book_order(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    sources: &[(PathBuf, PathBuf)],
+) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn std::error::Error>> {
+    let mut pages = Vec::new();
+    collect_pages(&source_tree(tgt_dir, sources), src_dir, &mut pages)?;
+    Ok(pages)
+}
+
+fn collect_pages(
+    parent: &Entry,
+    dir: &Path,
+    pages: &mut Vec<(PathBuf, PathBuf)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for name in ordered_names(parent, dir)? {
+        let entry = &parent.children[&name];
+        pages.extend(entry.page.clone());
+        collect_pages(entry, &dir.join(&name), pages)?;
+    }
+
+    Ok(())
+}
+
+/// Arranges the sources in a tree of entries following their folders
+fn source_tree(tgt_dir: &Path, sources: &[(PathBuf, PathBuf)]) -> Entry {
+    let mut root = Entry::default();
+    for (src_file, relative) in sources {
+        let mut entry = &mut root;
+        let name = relative.with_extension("");
+        let mut components = name.iter().peekable();
+        while let Some(component) = components.next() {
+            entry = entry
+                .children
+                .entry(component.to_string_lossy().into_owned())
+                .or_default();
+            if components.peek().is_none() {
+                let md_path = tgt_dir.join(relative).with_extension("md");
+                entry.page = Some((src_file.clone(), md_path));
+            }
+        }
+    }
+
+    root
+}
+
+fn render_entries(
+    parent: &Entry,
+    dir: &Path,
+    tgt_dir: &Path,
+    depth: usize,
+    content: &mut String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for name in ordered_names(parent, dir)? {
+        let entry = &parent.children[&name];
+        let indent = "    ".repeat(depth);
+        match &entry.page {
+            Some((src_file, md_path)) => {
+                let title = page_title(&fs::read_to_string(src_file)?)
+                    .unwrap_or_else(|| strip_number(&name).to_string());
+                let link = relative_link(tgt_dir, md_path);
+                content.push_str(&format!("{}- [{}]({})\n", indent, title, link));
+            }
+            None => content.push_str(&format!("{}- [{}]()\n", indent, strip_number(&name))),
+        }
+        render_entries(entry, &dir.join(&name), tgt_dir, depth + 1, content)?;
+    }
+
+    Ok(())
+}
+
+/// The names of the entries of a folder in chapter order
+fn ordered_names(parent: &Entry, dir: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut listed = Vec::new();
+    let order_file = dir.join(ORDER_FILE);
+    if order_file.is_file() {
+        for line in fs::read_to_string(&order_file)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let name = line.trim_end_matches('/').trim_end_matches(".lean");
+            if parent.children.contains_key(name) && !listed.iter().any(|n| n == name) {
+                listed.push(name.to_string());
+            }
+        }
+    }
+
+    let mut rest: Vec<String> = parent
+        .children
+        .keys()
+        .filter(|name| !listed.contains(name))
+        .cloned()
+        .collect();
+    rest.sort_by_key(|name| (number(name).unwrap_or(u64::MAX), name.clone()));
+
+    listed.extend(rest);
+    Ok(listed)
+}
+
+/// The first Markdown heading in the prose of a Lean file, including module docstrings
+pub(crate) fn page_title(content: &str) -> Option<String> {
+    let (blocks, _) = build_blocks(content).ok()?;
+    let prose = blocks
+        .iter()
+        .filter(|block| !block.is_code && !block.is_admonish);
+
+    for block in prose {
+        let mut in_fence = false;
+        for line in block.content.lines() {
+            // Module docstrings keep the `!` of their `/-!` opening
+            let line = line.trim_start_matches('!').trim();
+            if line.starts_with("```") {
+                in_fence = !in_fence;
+                continue;
+            }
+            if in_fence {
+                continue;
+            }
+
+            let title = line.trim_start_matches('#');
+            if title.len() < line.len() && title.starts_with(' ') && !title.trim().is_empty() {
+                return Some(title.trim().to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// The numeric prefix of a file name such as `01_Intro`
+fn number(name: &str) -> Option<u64> {
+    let digits: String = name.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// A file name without its numeric prefix and the separator following it
+fn strip_number(name: &str) -> &str {
+    let rest = name.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == name.len() {
+        return name;
+    }
+    let rest = rest.trim_start_matches(['_', '-', '.', ' ']);
+    if rest.is_empty() {
+        name
+    } else {
+        rest
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, PollWatcher, RecursiveMode, Watcher};
+
+use crate::lean2md_core::{process_directory_with_options, Options};
+
+/// Settings for watch mode
+#[derive(Debug, Clone)]
+pub struct WatchSettings {
+    /// How long the sources must be quiet before changed files are reconverted
+    pub debounce: Duration,
+    /// Poll the filesystem instead of using native notifications
+    pub poll: bool,
+    /// How often the filesystem is scanned when polling
+    pub poll_interval: Duration,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        WatchSettings {
+            debounce: Duration::from_millis(200),
+            poll: false,
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Converts `src_dir` and then keeps reconverting changed Lean files until interrupted
+///
+/// Native filesystem notifications are used where available, falling back to polling if
+/// they cannot be set up. Rapid successive saves are debounced, and conversion errors are
+/// reported without stopping the watcher. Each round of changes runs an incremental
+/// directory build that writes every file that converts.
+///
+/// # Arguments
+///
+/// * `src_dir` - Path to the source directory containing Lean files
+/// * `tgt_dir` - Path to the target directory where Markdown files are written
+/// * `options` - Conversion options
+/// * `settings` - Debouncing and polling settings
+///
+/// # Returns
+///
+/// Only returns if the watcher cannot be started or stops delivering events
+pub fn watch_directory(
+    src_dir: &Path,
+    tgt_dir: &Path,
+    options: &Options,
+    settings: &WatchSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let src_dir = fs::canonicalize(src_dir)?;
+
+    // A file that fails to convert must not hold back the others
+    let options = Options {
+        keep_going: true,
+        ..options.clone()
+    };
+
+    if let Err(e) = process_directory_with_options(&src_dir, tgt_dir, &options) {
+        eprintln!("Error: {}", e);
+    }
+
+    let (tx, rx) = channel();
+    let _watcher = start_watcher(&src_dir, tx, settings)?;
+    println!("Watching {} for changes", src_dir.display());
+
+    loop {
+        // The incremental build reconverts exactly the sources whose content changed and
+        // keeps links between pages, the summary and the build manifest up to date, which
+        // converting single files could not
+        wait_for_changes(&rx, settings.debounce)?;
+        if let Err(e) = process_directory_with_options(&src_dir, tgt_dir, &options) {
+            eprintln!("Error: {}", e);
+        }
+    }
+}
+
+/// Starts watching `src_dir`, polling if asked to or if native notifications fail
+///
+/// Native watchers can fail both on creation and when the directory is registered (e.g.
+/// when the inotify watch limit is reached), so both count as unavailable.
+fn start_watcher(
+    src_dir: &Path,
+    tx: Sender<notify::Result<Event>>,
+    settings: &WatchSettings,
+) -> notify::Result<Box<dyn Watcher>> {
+    if !settings.poll {
+        let native = notify::recommended_watcher(tx.clone()).and_then(|mut watcher| {
+            watcher.watch(src_dir, RecursiveMode::Recursive)?;
+            Ok(watcher)
+        });
+        match native {
+            Ok(watcher) => return Ok(Box::new(watcher)),
+            Err(e) => eprintln!(
+                "Filesystem notifications unavailable ({}), polling instead",
+                e
+            ),
+        }
+    }
+
+    let mut watcher = poll_watcher(tx, settings)?;
+    watcher.watch(src_dir, RecursiveMode::Recursive)?;
+    Ok(Box::new(watcher))
+}
+
+fn poll_watcher(
+    tx: Sender<notify::Result<Event>>,
+    settings: &WatchSettings,
+) -> notify::Result<PollWatcher> {
+    PollWatcher::new(
+        tx,
+        notify::Config::default().with_poll_interval(settings.poll_interval),
+    )
+}
+
+/// Waits for a change to the sources and returns once no event arrived for `debounce`
+fn wait_for_changes(
+    rx: &Receiver<notify::Result<Event>>,
+    debounce: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Block until the first relevant event, then wait until things are quiet
+    while !is_relevant(rx.recv()?) {}
+    loop {
+        match rx.recv_timeout(debounce) {
+            Ok(_) => {}
+            Err(RecvTimeoutError::Timeout) => return Ok(()),
+            Err(RecvTimeoutError::Disconnected) => return Err("File watcher stopped".into()),
+        }
+    }
+}
+
+/// Whether an event may change the generated pages
+///
+/// Removing or renaming a directory takes the Lean files inside with it, so removals and
+/// renames count whatever the path; other events only for Lean files.
+fn is_relevant(event: notify::Result<Event>) -> bool {
+    match event {
+        Ok(event) => match event.kind {
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => true,
+            EventKind::Create(_) | EventKind::Modify(_) => event
+                .paths
+                .iter()
+                .any(|path| path.extension().is_some_and(|ext| ext == "lean")),
+            _ => false,
+        },
+        Err(e) => {
+            eprintln!("Watch error: {}", e);
+            false
+        }
+    }
+}
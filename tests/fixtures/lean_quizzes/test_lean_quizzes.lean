@@ -0,0 +1,20 @@
+/-
+# Checked answers
+
+--@quiz:lean_checked
+lean_imports = ["Init"]
+
+[[questions]]
+type = "Lean"
+prompt.prompt = "Complete the proof."
+prompt.lean = "example : 2 + 2 = 4 :="
+answer.answer = "by decide"
+
+[[questions]]
+type = "ShortAnswer"
+prompt.prompt = "What is the keyword for definitions in Lean?"
+answer.answer = "def"
+--@quiz-end
+-/
+
+def double (n : Nat) : Nat := n + n
@@ -62,8 +62,6 @@ fn run_fixture_test(fixture_name: &str) {
                 // Check if generated quiz file exists
                 let generated_quiz_path = temp_out
                     .path()
-                    .parent()
-                    .unwrap()
                     .join("quizzes")
                     .join(format!("{}.toml", quiz_name));
 
@@ -204,3 +202,1421 @@ fn test_nested_code() {
 fn test_quizzes() {
     run_fixture_test("quizzes");
 }
+
+#[test]
+fn test_quizzes_inline_html() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(temp_in.path().join("test_quizzes.lean"), fixture_content).unwrap();
+
+    let options = lean2md::Options {
+        quiz_mode: lean2md::QuizMode::Html,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let actual = fs::read_to_string(temp_out.path().join("test_quizzes.md")).unwrap();
+    assert!(
+        actual.contains("<div class=\"quiz-placeholder\" data-quiz-name=\"lean_basics\""),
+        "Inline quiz missing: {}",
+        actual
+    );
+    assert!(actual.contains("&quot;answer&quot;:{&quot;answer&quot;:&quot;def&quot;}"));
+    assert!(!actual.contains("{{#quiz"));
+}
+
+#[test]
+fn test_no_quizzes_dir_without_quizzes() {
+    let temp_root = tempfile::tempdir().unwrap();
+    let src_dir = temp_root.path().join("src");
+    let tgt_dir = temp_root.path().join("out");
+    fs::create_dir_all(&src_dir).unwrap();
+    fs::write(src_dir.join("plain.lean"), "/- Text -/\ndef x := 1").unwrap();
+
+    lean2md::process_directory(&src_dir, &tgt_dir).unwrap();
+
+    assert!(tgt_dir.join("plain.md").exists());
+    assert!(!tgt_dir.join("quizzes").exists());
+    assert!(!temp_root.path().join("quizzes").exists());
+}
+
+#[test]
+fn test_quiz_links_from_subdirectory() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::create_dir_all(temp_in.path().join("chapter")).unwrap();
+    fs::write(temp_in.path().join("chapter/quiz.lean"), fixture_content).unwrap();
+
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+
+    let actual = fs::read_to_string(temp_out.path().join("chapter/quiz.md")).unwrap();
+    assert!(actual.contains("{{#quiz ../quizzes/lean_basics.toml}}"));
+    assert!(temp_out.path().join("quizzes/lean_basics.toml").exists());
+}
+
+#[test]
+fn test_quiz_links_for_relative_paths() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(temp_dir.path().join("q.lean"), fixture_content).unwrap();
+
+    // `lean2md q.lean` run next to the file puts the quizzes beside it
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_lean2md"))
+        .arg("q.lean")
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to execute process");
+    assert!(output.status.success());
+
+    let actual = fs::read_to_string(temp_dir.path().join("q.md")).unwrap();
+    assert!(actual.contains("{{#quiz quizzes/lean_basics.toml}}"));
+    assert!(temp_dir.path().join("quizzes/lean_basics.toml").exists());
+}
+
+#[test]
+fn test_question_bank() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(temp_in.path().join("test_quizzes.lean"), fixture_content).unwrap();
+
+    lean2md::export_question_bank(temp_in.path(), temp_out.path(), &Default::default()).unwrap();
+
+    let json = fs::read_to_string(temp_out.path().join("question_bank.json")).unwrap();
+    let questions: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(questions.as_array().unwrap().len(), 2);
+    assert_eq!(questions[0]["quiz"], "lean_basics");
+    assert_eq!(questions[0]["source"], "test_quizzes.lean");
+    assert_eq!(questions[0]["line"], 5);
+    assert_eq!(questions[0]["answers"][0], "def");
+    assert_eq!(questions[1]["type"], "MultipleChoice");
+    assert_eq!(questions[1]["line"], 11);
+    assert_eq!(questions[1]["distractors"][2], "==");
+
+    let key = fs::read_to_string(temp_out.path().join("answer_key.md")).unwrap();
+    assert!(key.contains("## lean_basics"));
+    assert!(key.contains("### Question 2 (MultipleChoice, line 11)"));
+    assert!(key.contains("**Answer:** :"));
+}
+
+#[test]
+fn test_question_bank_respects_source_selection() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::create_dir_all(temp_in.path().join("drafts")).unwrap();
+    fs::write(temp_in.path().join("quizzes.lean"), &fixture_content).unwrap();
+    fs::write(temp_in.path().join("drafts/quizzes.lean"), &fixture_content).unwrap();
+
+    let options = lean2md::Options {
+        exclude: vec!["drafts".to_string()],
+        ..Default::default()
+    };
+    let questions = lean2md::collect_questions(temp_in.path(), &options).unwrap();
+
+    assert_eq!(questions.len(), 2);
+    assert!(questions.iter().all(|q| q.source == "quizzes.lean"));
+}
+
+#[test]
+fn test_lean_quizzes() {
+    run_fixture_test("lean_quizzes");
+}
+
+#[test]
+fn test_lean_quiz_check_file() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let checks = tempfile::tempdir().unwrap();
+    let fixture_content =
+        fs::read_to_string("tests/fixtures/lean_quizzes/test_lean_quizzes.lean").unwrap();
+    fs::write(temp_in.path().join("checked.lean"), fixture_content).unwrap();
+
+    let options = lean2md::Options {
+        lean_check_dir: Some(checks.path().to_path_buf()),
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let check = fs::read_to_string(checks.path().join("lean_checked.lean")).unwrap();
+    assert_eq!(
+        check,
+        "-- Answer checks for quiz `lean_checked`, generated by lean2md. Do not edit.\n\
+         import Init\n\
+         \n\
+         -- Question 1 (line 7)\n\
+         section\n\
+         example : 2 + 2 = 4 :=\n  by decide\n\
+         end\n"
+    );
+    assert!(!temp_out.path().join("quizzes/lean_checked.lean").exists());
+}
+
+#[test]
+fn test_quiz_content_kept_verbatim() {
+    // Quizzes without Lean questions are not parsed
+    let input = "/-\n--@quiz:q1\nnot = valid = toml\n--@quiz-end\n-/";
+    let conversion = lean2md::convert_str(input, "", &lean2md::Options::default()).unwrap();
+    assert_eq!(conversion.quiz_files[0].content, "not = valid = toml\n");
+
+    // Comments and key order survive the rewriting of Lean questions
+    let input = "/-\n--@quiz:q2\n# Warm-up\n[[questions]]\nanswer.answer = \"rfl\"\n\
+                 type = \"Lean\"  # checked\nprompt.prompt = \"Prove it.\"\n--@quiz-end\n-/";
+    let conversion = lean2md::convert_str(input, "", &lean2md::Options::default()).unwrap();
+    assert_eq!(
+        conversion.quiz_files[0].content,
+        "# Warm-up\n[[questions]]\nanswer.answer = \"rfl\"\n\
+         type = \"ShortAnswer\"  # checked\nprompt.prompt = \"Prove it.\"\n"
+    );
+}
+
+#[test]
+fn test_cli_switches_off_configured_options() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    fs::write(temp_dir.path().join("lean2md.toml"), "toc = true\n").unwrap();
+    fs::write(temp_dir.path().join("a.lean"), "/- # A\n## B\n## C -/\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_lean2md"))
+        .args(["convert", "a.lean", "--toc=false"])
+        .current_dir(temp_dir.path())
+        .output()
+        .expect("Failed to execute process");
+    assert!(output.status.success());
+
+    let actual = fs::read_to_string(temp_dir.path().join("a.md")).unwrap();
+    assert!(!actual.contains("- [B](#b)"));
+}
+
+#[test]
+fn test_version_output() {
+    let output = std::process::Command::new("cargo")
+        .args(["run", "--", "--version"])
+        .output()
+        .expect("Failed to execute process");
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("lean2md version {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn test_invalid_arguments_fail() {
+    let output = std::process::Command::new("cargo")
+        .args(["run", "--", "convert", "--no-such-flag"])
+        .output()
+        .expect("Failed to execute process");
+
+    assert!(!output.status.success(), "Command succeeded: {:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage"));
+}
+
+#[test]
+fn test_shell_completions() {
+    let output = std::process::Command::new("cargo")
+        .args(["run", "--", "completions", "bash"])
+        .output()
+        .expect("Failed to execute process");
+
+    assert!(output.status.success(), "Command failed: {:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("lean2md"));
+}
+
+#[test]
+fn test_config_discovery() {
+    let temp_root = tempfile::tempdir().unwrap();
+    let nested = temp_root.path().join("a/b");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(
+        temp_root.path().join("lean2md.toml"),
+        "src = \"Book\"\n\
+         tgt = \"book/src\"\n\
+         quiz_mode = \"fenced\"\n\
+         exclude = [\"Scratch\"]\n\
+         front_matter = \"header.md\"\n\
+         \n\
+         [admonish]\n\
+         kind = \"note\"\n\
+         title = \"Definition\"\n",
+    )
+    .unwrap();
+    fs::write(temp_root.path().join("header.md"), "<!-- generated -->\n").unwrap();
+
+    let config = lean2md::Config::discover(&nested).unwrap().unwrap();
+    assert_eq!(
+        config.resolve(config.src.as_ref().unwrap()),
+        std::path::absolute(temp_root.path().join("Book")).unwrap()
+    );
+
+    let mut options = lean2md::Options::default();
+    config.apply(&mut options).unwrap();
+    assert_eq!(options.quiz_mode, lean2md::QuizMode::Fenced);
+
+    let src_dir = temp_root.path().join("Book");
+    let tgt_dir = temp_root.path().join("book/src");
+    fs::create_dir_all(src_dir.join("Scratch")).unwrap();
+    fs::write(
+        src_dir.join("Intro.lean"),
+        "/-- Adds one -/ --+\ndef f := 1",
+    )
+    .unwrap();
+    fs::write(src_dir.join("Scratch/Test.lean"), "def scratch := 0").unwrap();
+
+    lean2md::process_directory_with_options(&src_dir, &tgt_dir, &options).unwrap();
+
+    let intro = fs::read_to_string(tgt_dir.join("Intro.md")).unwrap();
+    assert!(intro.starts_with("<!-- generated -->\n\n"));
+    assert!(
+        intro.contains("```admonish note collapsible = false, title = \"Definition\"\nAdds one")
+    );
+    assert!(!tgt_dir.join("Scratch").exists());
+}
+
+#[test]
+fn test_config_from_lakefile() {
+    let temp_root = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_root.path().join("lakefile.toml"),
+        "name = \"book\"\n\n[lean2md]\nquiz_mode = \"html\"\n",
+    )
+    .unwrap();
+
+    let config = lean2md::Config::discover(temp_root.path())
+        .unwrap()
+        .unwrap();
+    assert_eq!(config.quiz_mode, Some(lean2md::QuizMode::Html));
+}
+
+#[test]
+fn test_stdin_to_stdout() {
+    use std::io::Write;
+
+    let mut child = std::process::Command::new("cargo")
+        .args(["run", "--", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute process");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"/- Piped comment -/\ndef piped := 1\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success(), "Command failed: {:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Piped comment\n\n```lean\ndef piped := 1\n```\n"
+    );
+}
+
+#[test]
+fn test_stdin_flag_with_target() {
+    use std::io::Write;
+
+    let temp_out = tempfile::tempdir().unwrap();
+    let tgt = temp_out.path().join("out.md");
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_lean2md"))
+        .args(["convert", "--stdin"])
+        .arg(&tgt)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute process");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"def piped := 1\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success(), "Command failed: {:?}", output);
+    assert_eq!(
+        fs::read_to_string(&tgt).unwrap(),
+        "```lean\ndef piped := 1\n```\n"
+    );
+}
+
+#[test]
+fn test_failed_stdin_conversion_leaves_no_target() {
+    use std::io::Write;
+
+    let temp_out = tempfile::tempdir().unwrap();
+    let tgt = temp_out.path().join("out.md");
+    let mut child = std::process::Command::new("cargo")
+        .args(["run", "--", "-"])
+        .arg(&tgt)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to execute process");
+    // Quizzes from standard input need a quiz directory
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"/-\n--@quiz:q\n[[questions]]\n--@quiz-end\n-/\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success(), "Command succeeded: {:?}", output);
+    assert!(!tgt.exists());
+}
+
+#[test]
+fn test_stream_with_quiz_dir() {
+    let quiz_dir = tempfile::tempdir().unwrap();
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    let options = lean2md::Options {
+        quiz_dir: Some(quiz_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+
+    lean2md::process_stream(&mut fixture_content.as_bytes(), &mut output, &options).unwrap();
+
+    let markdown = String::from_utf8(output).unwrap();
+    assert!(markdown.contains(&format!(
+        "{{{{#quiz {}/lean_basics.toml}}}}",
+        quiz_dir.path().display()
+    )));
+    assert!(quiz_dir.path().join("lean_basics.toml").exists());
+}
+
+#[test]
+fn test_check_directory() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(temp_in.path().join("test_quizzes.lean"), fixture_content).unwrap();
+    let options = lean2md::Options::default();
+
+    // Nothing generated yet
+    let mut report = Vec::new();
+    assert!(
+        !lean2md::check_directory(temp_in.path(), temp_out.path(), &options, &mut report).unwrap()
+    );
+    assert!(!temp_out.path().join("test_quizzes.md").exists());
+
+    // Freshly generated
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    let mut report = Vec::new();
+    assert!(
+        lean2md::check_directory(temp_in.path(), temp_out.path(), &options, &mut report).unwrap()
+    );
+
+    // Stale quiz file
+    let quiz_path = temp_out.path().join("quizzes/lean_basics.toml");
+    let quiz = fs::read_to_string(&quiz_path).unwrap();
+    fs::write(
+        &quiz_path,
+        quiz.replace("answer.answer = \"def\"", "answer.answer = \"let\""),
+    )
+    .unwrap();
+    let mut report = Vec::new();
+    assert!(
+        !lean2md::check_directory(temp_in.path(), temp_out.path(), &options, &mut report).unwrap()
+    );
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("-answer.answer = \"let\"\n+answer.answer = \"def\""));
+    assert!(report.contains("1 generated file(s) are out of date"));
+}
+
+#[test]
+fn test_check_reports_orphaned_outputs() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(temp_in.path().join("kept.lean"), "def x := 1").unwrap();
+    fs::write(temp_in.path().join("deleted.lean"), "def y := 2").unwrap();
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    fs::remove_file(temp_in.path().join("deleted.lean")).unwrap();
+
+    let options = lean2md::Options::default();
+    let mut report = Vec::new();
+    assert!(
+        !lean2md::check_directory(temp_in.path(), temp_out.path(), &options, &mut report).unwrap()
+    );
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains(&format!(
+        "--- {}\n+++ /dev/null",
+        temp_out.path().join("deleted.md").display()
+    )));
+    assert!(report.contains("1 generated file(s) are out of date"));
+
+    // Also after a build without --prune, which remembers the orphaned page
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    let mut report = Vec::new();
+    assert!(
+        !lean2md::check_directory(temp_in.path(), temp_out.path(), &options, &mut report).unwrap()
+    );
+}
+
+#[test]
+fn test_dry_run_directory() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let tgt_dir = temp_out.path().join("book");
+    let fixture_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(temp_in.path().join("test_quizzes.lean"), fixture_content).unwrap();
+    fs::write(temp_in.path().join("plain.lean"), "def x := 1").unwrap();
+    fs::create_dir_all(&tgt_dir).unwrap();
+    fs::write(tgt_dir.join("plain.md"), "outdated\n").unwrap();
+
+    let mut report = Vec::new();
+    lean2md::dry_run_directory(
+        temp_in.path(),
+        &tgt_dir,
+        &lean2md::Options::default(),
+        &mut report,
+    )
+    .unwrap();
+    let report = String::from_utf8(report).unwrap();
+
+    assert!(report.contains(&format!(
+        "create dir  {}",
+        tgt_dir.join("quizzes").display()
+    )));
+    assert!(report.contains(&format!(
+        "overwrite   {}",
+        tgt_dir.join("plain.md").display()
+    )));
+    assert!(report.contains(&format!(
+        "create      {}",
+        tgt_dir.join("quizzes/lean_basics.toml").display()
+    )));
+    assert!(report.contains("2 file(s) would be created, 1 overwritten"));
+
+    // Nothing was touched
+    assert_eq!(
+        fs::read_to_string(tgt_dir.join("plain.md")).unwrap(),
+        "outdated\n"
+    );
+    assert!(!tgt_dir.join("quizzes").exists());
+    assert!(!tgt_dir.join("test_quizzes.md").exists());
+}
+
+#[test]
+fn test_dry_run_lists_pruned_files() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(temp_in.path().join("kept.lean"), "def x := 1").unwrap();
+    fs::write(temp_in.path().join("deleted.lean"), "def y := 2").unwrap();
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    fs::remove_file(temp_in.path().join("deleted.lean")).unwrap();
+
+    let options = lean2md::Options {
+        prune: true,
+        ..Default::default()
+    };
+    let mut report = Vec::new();
+    lean2md::dry_run_directory(temp_in.path(), temp_out.path(), &options, &mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+
+    assert!(report.contains(&format!(
+        "remove      {}",
+        temp_out.path().join("deleted.md").display()
+    )));
+    assert!(report.contains(&format!(
+        "update      {}",
+        temp_out.path().join(lean2md::MANIFEST_FILE).display()
+    )));
+    assert!(report.contains("1 unchanged, 1 removed"));
+    assert!(temp_out.path().join("deleted.md").exists());
+}
+
+#[test]
+fn test_watch_reconverts_changed_files() {
+    use std::time::{Duration, Instant};
+
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(20) {
+            if condition() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let md_path = temp_out.path().join("watched.md");
+    fs::write(temp_in.path().join("watched.lean"), "def before := 1").unwrap();
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_lean2md"))
+        .args(["watch", "--poll", "--debounce", "50"])
+        .arg(temp_in.path())
+        .arg(temp_out.path())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to execute process");
+
+    let initial = wait_for(|| md_path.exists());
+
+    // A broken file must not stop the watcher
+    fs::write(temp_in.path().join("broken.lean"), "/- unclosed").unwrap();
+    std::thread::sleep(Duration::from_millis(1100));
+    fs::write(temp_in.path().join("watched.lean"), "def after := 2").unwrap();
+    let updated = wait_for(|| {
+        fs::read_to_string(&md_path).is_ok_and(|content| content.contains("def after := 2"))
+    });
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(initial, "Initial conversion did not happen");
+    assert!(updated, "Changed file was not reconverted");
+}
+
+#[test]
+fn test_incremental_build_skips_unchanged_files() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(temp_in.path().join("a.lean"), "def a := 1").unwrap();
+    fs::write(temp_in.path().join("b.lean"), "def b := 1").unwrap();
+
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    assert!(temp_out.path().join(lean2md::MANIFEST_FILE).exists());
+
+    // Mark both outputs so we can tell whether they were rewritten
+    fs::write(temp_out.path().join("a.md"), "kept\n").unwrap();
+    fs::write(temp_out.path().join("b.md"), "kept\n").unwrap();
+    fs::write(temp_in.path().join("b.lean"), "def b := 2").unwrap();
+
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    assert_eq!(
+        fs::read_to_string(temp_out.path().join("a.md")).unwrap(),
+        "kept\n"
+    );
+    assert!(fs::read_to_string(temp_out.path().join("b.md"))
+        .unwrap()
+        .contains("def b := 2"));
+
+    // Different options invalidate the cache
+    let options = lean2md::Options {
+        front_matter: Some("<!-- generated -->".to_string()),
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+    assert!(fs::read_to_string(temp_out.path().join("a.md"))
+        .unwrap()
+        .starts_with("<!-- generated -->"));
+}
+
+#[test]
+fn test_unchanged_outputs_are_not_rewritten() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(temp_in.path().join("a.lean"), "def a := 1").unwrap();
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+
+    let md_path = temp_out.path().join("a.md");
+    let old_time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&md_path)
+        .unwrap()
+        .set_modified(old_time)
+        .unwrap();
+
+    let options = lean2md::Options {
+        force: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+    assert_eq!(
+        fs::metadata(&md_path).unwrap().modified().unwrap(),
+        old_time
+    );
+}
+
+#[test]
+fn test_prune_removes_orphaned_outputs() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let quiz_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::create_dir_all(temp_in.path().join("chapter")).unwrap();
+    fs::write(temp_in.path().join("chapter/old.lean"), "def old := 1").unwrap();
+    fs::write(temp_in.path().join("quiz.lean"), &quiz_content).unwrap();
+    fs::write(temp_out.path().join("handwritten.md"), "# Mine\n").unwrap();
+
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    assert!(temp_out.path().join("chapter/old.md").exists());
+    assert!(temp_out.path().join("quizzes/lean_basics.toml").exists());
+
+    // Delete a source and the quiz; a build without --prune keeps the files around
+    fs::remove_file(temp_in.path().join("chapter/old.lean")).unwrap();
+    fs::write(temp_in.path().join("quiz.lean"), "/- No quiz any more -/").unwrap();
+    lean2md::process_directory(temp_in.path(), temp_out.path()).unwrap();
+    assert!(temp_out.path().join("chapter/old.md").exists());
+
+    let options = lean2md::Options {
+        prune: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    assert!(!temp_out.path().join("chapter").exists());
+    assert!(!temp_out.path().join("quizzes").exists());
+    assert!(temp_out.path().join("quiz.md").exists());
+    assert!(temp_out.path().join("handwritten.md").exists());
+}
+
+#[test]
+fn test_prune_removes_quizzes_in_quiz_directory() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let quiz_dir = tempfile::tempdir().unwrap();
+    let quiz_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(temp_in.path().join("quiz.lean"), &quiz_content).unwrap();
+    fs::write(quiz_dir.path().join("handwritten.toml"), "").unwrap();
+
+    let options = lean2md::Options {
+        quiz_dir: Some(quiz_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+    assert!(quiz_dir.path().join("lean_basics.toml").exists());
+
+    fs::write(temp_in.path().join("quiz.lean"), "/- No quiz any more -/").unwrap();
+    let options = lean2md::Options {
+        prune: true,
+        ..options
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    assert!(!quiz_dir.path().join("lean_basics.toml").exists());
+    assert!(quiz_dir.path().join("handwritten.toml").exists());
+}
+
+#[test]
+fn test_prune_stays_inside_target_directory() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_root = tempfile::tempdir().unwrap();
+    let temp_out = temp_root.path().join("book");
+    fs::write(temp_in.path().join("a.lean"), "def a := 1").unwrap();
+    fs::write(temp_root.path().join("outside.md"), "# Mine\n").unwrap();
+    lean2md::process_directory(temp_in.path(), &temp_out).unwrap();
+
+    // A tampered manifest must not make --prune delete files outside the target directory
+    let manifest_path = temp_out.join(lean2md::MANIFEST_FILE);
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    let outside = temp_root.path().join("outside.md");
+    manifest["orphaned"] = serde_json::json!(["../outside.md", outside]);
+    fs::write(&manifest_path, manifest.to_string()).unwrap();
+
+    let options = lean2md::Options {
+        prune: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), &temp_out, &options).unwrap();
+
+    assert!(temp_root.path().join("outside.md").exists());
+    assert!(temp_out.join("a.md").exists());
+}
+
+#[test]
+fn test_parallel_build_reports_all_failures() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    for name in ["a", "c", "e"] {
+        fs::write(temp_in.path().join(format!("{}.lean", name)), "def x := 1").unwrap();
+    }
+    fs::write(temp_in.path().join("b.lean"), "/- unclosed").unwrap();
+    fs::write(temp_in.path().join("d.lean"), "/- unclosed").unwrap();
+
+    let options = lean2md::Options {
+        jobs: Some(4),
+        ..Default::default()
+    };
+    let error = lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options)
+        .unwrap_err();
+    let errors = error
+        .downcast_ref::<lean2md::ConversionErrors>()
+        .expect("Expected the errors of all files");
+    let failed: Vec<_> = errors
+        .failures
+        .iter()
+        .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(failed, vec!["b.lean", "d.lean"]);
+    assert!(error
+        .to_string()
+        .starts_with("2 file(s) failed to convert:"));
+    assert_eq!(fs::read_dir(temp_out.path()).unwrap().count(), 0);
+
+    // Once fixed, a parallel build converts every file
+    fs::write(temp_in.path().join("b.lean"), "def b := 1").unwrap();
+    fs::write(temp_in.path().join("d.lean"), "def d := 1").unwrap();
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+    for name in ["a", "b", "c", "d", "e"] {
+        assert!(temp_out.path().join(format!("{}.md", name)).exists());
+    }
+}
+
+#[test]
+fn test_keep_going_writes_converted_files() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(temp_in.path().join("a.lean"), "def a := 1").unwrap();
+    fs::write(temp_in.path().join("b.lean"), "/- unclosed").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_lean2md"))
+        .arg("build")
+        .arg(temp_in.path())
+        .arg(temp_out.path())
+        .arg("--keep-going")
+        .output()
+        .expect("Failed to execute process");
+
+    assert!(!output.status.success(), "Command succeeded: {:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("converted  1"),
+        "Unexpected output: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("failed     1"),
+        "Unexpected output: {}",
+        stdout
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("b.lean: Unclosed comment block"));
+    assert!(temp_out.path().join("a.md").exists());
+    assert!(!temp_out.path().join("b.md").exists());
+
+    // Without --keep-going nothing is written
+    let temp_out = tempfile::tempdir().unwrap();
+    let options = lean2md::Options::default();
+    assert!(
+        lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).is_err()
+    );
+    assert!(!temp_out.path().join("a.md").exists());
+}
+
+#[test]
+fn test_include_exclude_and_ignore_files() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    for path in [
+        "Book/Intro.lean",
+        "Book/Chapter/Basics.lean",
+        "Book/Chapter/BasicsTest.lean",
+        "Book/Scratch/Draft.lean",
+        "Book/Generated.lean",
+        ".lake/packages/Dep.lean",
+        "Other.lean",
+    ] {
+        let file = temp_in.path().join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, "def x := 1").unwrap();
+    }
+    fs::write(temp_in.path().join(".gitignore"), "Generated.lean\n").unwrap();
+    fs::write(temp_in.path().join("Book/.lean2mdignore"), "Scratch/\n").unwrap();
+
+    let options = lean2md::Options {
+        include: vec!["Book".to_string()],
+        exclude: vec!["**/*Test.lean".to_string()],
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    assert!(temp_out.path().join("Book/Intro.md").exists());
+    assert!(temp_out.path().join("Book/Chapter/Basics.md").exists());
+    assert!(!temp_out.path().join("Book/Chapter/BasicsTest.md").exists());
+    assert!(!temp_out.path().join("Book/Scratch").exists());
+    assert!(!temp_out.path().join("Book/Generated.md").exists());
+    assert!(!temp_out.path().join(".lake").exists());
+    assert!(!temp_out.path().join("Other.md").exists());
+
+    // Ignore files can be switched off
+    let options = lean2md::Options {
+        no_ignore: true,
+        ..options
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+    assert!(temp_out.path().join("Book/Scratch/Draft.md").exists());
+    assert!(temp_out.path().join("Book/Generated.md").exists());
+}
+
+#[test]
+fn test_summary_generation() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    for (path, content) in [
+        ("Intro.lean", "/-! # Welcome\nAbout this book -/"),
+        ("02_Logic.lean", "/- # Logic -/"),
+        (
+            "02_Logic/Props.lean",
+            "/-\n```lean\n#check True\n```\n## Propositions\n-/",
+        ),
+        ("02_Logic/Quantifiers.lean", "def q := 1"),
+        ("01_Basics/Nat.lean", "/- # Natural Numbers -/"),
+        ("Appendix.lean", "/- # Appendix -/"),
+    ] {
+        let file = temp_in.path().join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, content).unwrap();
+    }
+    fs::write(
+        temp_in.path().join(lean2md::ORDER_FILE),
+        "# Front matter first\nIntro.lean\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_in.path().join("02_Logic").join(lean2md::ORDER_FILE),
+        "Quantifiers\n",
+    )
+    .unwrap();
+
+    let options = lean2md::Options {
+        summary: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let summary = fs::read_to_string(temp_out.path().join(lean2md::SUMMARY_FILE)).unwrap();
+    assert_eq!(
+        summary,
+        "# Summary\n\n\
+         - [Welcome](Intro.md)\n\
+         - [Basics]()\n    \
+             - [Natural Numbers](01_Basics/Nat.md)\n\
+         - [Logic](02_Logic.md)\n    \
+             - [Quantifiers](02_Logic/Quantifiers.md)\n    \
+             - [Propositions](02_Logic/Props.md)\n\
+         - [Appendix](Appendix.md)\n"
+    );
+
+    // The summary is part of what check compares
+    let mut out = Vec::new();
+    assert!(lean2md::check_directory(temp_in.path(), temp_out.path(), &options, &mut out).unwrap());
+}
+
+#[test]
+fn test_init_book() {
+    let temp_root = tempfile::tempdir().unwrap();
+    let lean_dir = temp_root.path().join("MyBook");
+    let book_dir = temp_root.path().join("book");
+    fs::create_dir_all(&lean_dir).unwrap();
+    fs::write(
+        lean_dir.join("Intro.lean"),
+        "/- # Introduction -/\ndef x := 1",
+    )
+    .unwrap();
+
+    lean2md::init_book(&lean_dir, &book_dir).unwrap();
+
+    let book_toml = fs::read_to_string(book_dir.join("book.toml")).unwrap();
+    assert!(book_toml.contains("title = \"MyBook\""));
+    assert!(book_toml.contains("[preprocessor.quiz]"));
+    assert!(book_toml.contains("[preprocessor.admonish]"));
+    assert!(book_toml.contains(lean2md::LEAN_HIGHLIGHT_FILE));
+    assert!(book_dir.join(lean2md::LEAN_HIGHLIGHT_FILE).exists());
+    assert_eq!(
+        fs::read_to_string(book_dir.join("src/SUMMARY.md")).unwrap(),
+        "# Summary\n\n- [Introduction](Intro.md)\n"
+    );
+    assert!(book_dir.join("src/Intro.md").exists());
+
+    // The written configuration lets `lean2md build` update the book
+    let config = lean2md::Config::load(&book_dir.join(lean2md::CONFIG_FILE)).unwrap();
+    assert_eq!(
+        std::path::absolute(config.resolve(config.src.as_ref().unwrap())).unwrap(),
+        std::path::absolute(book_dir.join("../MyBook")).unwrap()
+    );
+    assert_eq!(config.summary, Some(true));
+
+    // An existing book is never overwritten
+    assert!(lean2md::init_book(&lean_dir, &book_dir).is_err());
+}
+
+#[test]
+fn test_init_book_keeps_existing_highlight_script() {
+    let temp_root = tempfile::tempdir().unwrap();
+    let book_dir = temp_root.path().join("book");
+    fs::create_dir_all(&book_dir).unwrap();
+    let script = book_dir.join(lean2md::LEAN_HIGHLIGHT_FILE);
+    fs::write(&script, "// customised").unwrap();
+
+    assert!(lean2md::init_book(temp_root.path(), &book_dir).is_err());
+    assert_eq!(fs::read_to_string(&script).unwrap(), "// customised");
+    assert!(!book_dir.join("book.toml").exists());
+}
+
+#[test]
+fn test_mdbook_preprocessor() {
+    let book_root = tempfile::tempdir().unwrap();
+    let src_dir = book_root.path().join("src");
+    fs::create_dir_all(src_dir.join("lean")).unwrap();
+    let quiz_content = fs::read_to_string("tests/fixtures/quizzes/test_quizzes.lean").unwrap();
+    fs::write(
+        src_dir.join("lean/Basics.lean"),
+        "/- # Included -/\ndef y := 2",
+    )
+    .unwrap();
+
+    let input = serde_json::json!([
+        {
+            "root": book_root.path(),
+            "config": {
+                "book": { "src": "src" },
+                "preprocessor": {
+                    "lean2md": { "command": "lean2md mdbook-preprocessor", "admonish": { "kind": "note" } }
+                }
+            },
+            "renderer": "html",
+            "mdbook_version": "0.4.40"
+        },
+        {
+            "sections": [
+                { "Chapter": {
+                    "name": "Quizzes", "content": quiz_content, "number": [1],
+                    "sub_items": [
+                        { "Chapter": {
+                            "name": "Intro", "content": "# Intro\n\n{{#lean2md lean/Basics.lean}}\n",
+                            "number": [1, 1], "sub_items": [], "path": "intro.md",
+                            "source_path": "intro.md", "parent_names": ["Quizzes"]
+                        } }
+                    ],
+                    "path": "Quizzes.lean", "source_path": "Quizzes.lean", "parent_names": []
+                } },
+                "Separator"
+            ],
+            "__non_exhaustive": null
+        }
+    ]);
+
+    let mut output = Vec::new();
+    lean2md::run_preprocessor(&mut input.to_string().as_bytes(), &mut output).unwrap();
+    let book: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+    let quizzes = &book["sections"][0]["Chapter"];
+    assert!(quizzes["content"]
+        .as_str()
+        .unwrap()
+        .contains("{{#quiz quizzes/lean_basics.toml}}"));
+    assert!(src_dir.join("quizzes/lean_basics.toml").exists());
+
+    let intro = quizzes["sub_items"][0]["Chapter"]["content"]
+        .as_str()
+        .unwrap();
+    assert_eq!(intro, "# Intro\n\n# Included\n\n```lean\ndef y := 2\n```\n");
+    assert_eq!(book["sections"][1], "Separator");
+
+    assert!(lean2md::supports_renderer("html"));
+}
+
+#[test]
+fn test_import_links() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    let src_dir = temp_in.path().join("Book");
+    fs::create_dir_all(src_dir.join("Logic")).unwrap();
+    fs::write(src_dir.join("Basics.lean"), "def b := 1").unwrap();
+    fs::write(
+        src_dir.join("Logic/Props.lean"),
+        "/- Copyright notice -/\n\
+         import Mathlib.Logic.Basic\n\
+         import Book.Basics -- the basics\n\
+         \n\
+         /- # Propositions -/\n\
+         def p := True",
+    )
+    .unwrap();
+
+    let options = lean2md::Options {
+        import_links: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(&src_dir, temp_out.path(), &options).unwrap();
+
+    let props = fs::read_to_string(temp_out.path().join("Logic/Props.md")).unwrap();
+    assert!(
+        props.starts_with("**Depends on:** [Book.Basics](../Basics.md)\n\n"),
+        "Unexpected page: {}",
+        props
+    );
+    let basics = fs::read_to_string(temp_out.path().join("Basics.md")).unwrap();
+    assert!(!basics.contains("Depends on"));
+
+    // Adding a module invalidates the pages that may link to it
+    fs::write(
+        src_dir.join("Logic/Props.lean"),
+        "import Book.Extra\ndef p := True",
+    )
+    .unwrap();
+    lean2md::process_directory_with_options(&src_dir, temp_out.path(), &options).unwrap();
+    fs::write(src_dir.join("Extra.lean"), "def e := 1").unwrap();
+    lean2md::process_directory_with_options(&src_dir, temp_out.path(), &options).unwrap();
+    let props = fs::read_to_string(temp_out.path().join("Logic/Props.md")).unwrap();
+    assert!(props.starts_with("**Depends on:** [Book.Extra](../Extra.md)\n\n"));
+}
+
+#[test]
+fn test_import_links_leave_out_external_modules() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_in.path().join("Main.lean"),
+        "import Mathlib.Tactic\nimport Std.Data.List\ndef m := 1",
+    )
+    .unwrap();
+    fs::write(temp_in.path().join("Tactic.lean"), "def t := 1").unwrap();
+    fs::write(temp_in.path().join("List.lean"), "def l := 1").unwrap();
+
+    let options = lean2md::Options {
+        import_links: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let main = fs::read_to_string(temp_out.path().join("Main.md")).unwrap();
+    assert!(!main.contains("Depends on"), "Unexpected page: {}", main);
+}
+
+#[test]
+fn test_auto_link_declarations() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::create_dir_all(temp_in.path().join("Numbers")).unwrap();
+    fs::write(
+        temp_in.path().join("Numbers/Pos.lean"),
+        "/- Positive numbers -/\n\
+         structure NatPos where\n  val : Nat\n\n\
+         @[simp] theorem NatPos.val_pos (n : NatPos) : True := trivial\n\
+         instance : Inhabited NatPos := ⟨⟨1⟩⟩\n\
+         /- See `val_pos`. -/",
+    )
+    .unwrap();
+    fs::write(
+        temp_in.path().join("Intro.lean"),
+        "/- A `NatPos` is positive, see `NatPos.val_pos`; `Unknown` and ``NatPos`` stay.\n\
+         ```lean\n#check `NatPos`\n```\n-/\n\
+         def one : Nat := 1",
+    )
+    .unwrap();
+
+    let options = lean2md::Options {
+        auto_link: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let intro = fs::read_to_string(temp_out.path().join("Intro.md")).unwrap();
+    assert_eq!(
+        intro,
+        "A [`NatPos`](Numbers/Pos.md#NatPos) is positive, see \
+         [`NatPos.val_pos`](Numbers/Pos.md#NatPos.val_pos); `Unknown` and ``NatPos`` stay.\n\
+         ```lean\n#check `NatPos`\n```\n\n\
+         <a id=\"one\"></a>\n\n\
+         ```lean\ndef one : Nat := 1\n```\n"
+    );
+
+    let pos = fs::read_to_string(temp_out.path().join("Numbers/Pos.md")).unwrap();
+    assert!(pos.contains(
+        "<a id=\"NatPos\"></a>\n<a id=\"NatPos.val_pos\"></a>\n\n```lean\nstructure NatPos"
+    ));
+    assert!(pos.contains("See [`val_pos`](#NatPos.val_pos)."));
+}
+
+#[test]
+fn test_declaration_index_page() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_in.path().join("Pos.lean"),
+        "/-- A positive natural number.\n\n  More details. -/\n\
+         structure NatPos where\n  val : Nat\n\n\
+         /-- Positivity of the value -/\n\
+         @[simp]\n\
+         theorem NatPos.val_pos (n : NatPos) : True := trivial\n\
+         def double (n : Nat) := 2 * n\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_in.path().join("Sign.lean"),
+        "/-- The sign of an integer -/ --+\ninductive Sign where\n  | pos | neg\n",
+    )
+    .unwrap();
+
+    let options = lean2md::Options {
+        declaration_index: true,
+        summary: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let index = fs::read_to_string(temp_out.path().join(lean2md::DECLARATIONS_FILE)).unwrap();
+    assert_eq!(
+        index,
+        "# Declaration Index\n\
+         \n## Definitions\n\
+         \n### Root namespace\n\n\
+         - [`double`](Pos.md#double)\n\
+         \n## Theorems\n\
+         \n### `NatPos`\n\n\
+         - [`NatPos.val_pos`](Pos.md#NatPos.val_pos): Positivity of the value\n\
+         \n## Structures\n\
+         \n### Root namespace\n\n\
+         - [`NatPos`](Pos.md#NatPos): A positive natural number.\n\
+         \n## Inductive Types\n\
+         \n### Root namespace\n\n\
+         - [`Sign`](Sign.md#Sign): The sign of an integer\n"
+    );
+
+    // The links point at anchors in the pages
+    let pos = fs::read_to_string(temp_out.path().join("Pos.md")).unwrap();
+    assert!(pos.contains("<a id=\"NatPos.val_pos\"></a>"));
+    let summary = fs::read_to_string(temp_out.path().join(lean2md::SUMMARY_FILE)).unwrap();
+    assert!(summary.ends_with("- [Declaration Index](declarations.md)\n"));
+}
+
+#[test]
+fn test_declaration_index_after_mutual_block() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_in.path().join("Foo.lean"),
+        "namespace Foo\n\
+         mutual\n\
+         def even : Nat → Bool\n  | 0 => true\n  | n + 1 => odd n\n\
+         def odd : Nat → Bool\n  | 0 => false\n  | n + 1 => even n\n\
+         end\n\
+         def baz := 1\n\
+         end Foo\n",
+    )
+    .unwrap();
+
+    let options = lean2md::Options {
+        declaration_index: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let index = fs::read_to_string(temp_out.path().join(lean2md::DECLARATIONS_FILE)).unwrap();
+    assert!(index.contains("- [`Foo.baz`](Foo.md#Foo.baz)"), "{}", index);
+    assert!(!index.contains("Root namespace"), "{}", index);
+}
+
+#[test]
+fn test_namespaces() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_in.path().join("Shapes.lean"),
+        "namespace Shape --#\n\
+         /-- The area of a square -/\n\
+         def square (a : Nat) := a * a\n\
+         /- Circles are approximated. -/\n\
+         namespace Circle\n\
+         def area (r : Nat) := 3 * r * r\n\
+         end Circle\n\
+         end Shape --#\n\
+         /- Back at the top. -/\n\
+         theorem square_pos : True := trivial\n",
+    )
+    .unwrap();
+
+    let options = lean2md::Options {
+        namespaces: lean2md::NamespaceStyle::Headings,
+        declaration_index: true,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+
+    let page = fs::read_to_string(temp_out.path().join("Shapes.md")).unwrap();
+    assert_eq!(page.matches("## Namespace `Shape`").count(), 1);
+    assert!(page.contains("## Namespace `Shape`\n\n<a id=\"Shape.square\"></a>"));
+    assert!(page.contains("<a id=\"Shape.Circle.area\"></a>"));
+    assert!(page.contains("<a id=\"square_pos\"></a>"));
+
+    // Declarations are listed by their full names
+    let index = fs::read_to_string(temp_out.path().join(lean2md::DECLARATIONS_FILE)).unwrap();
+    assert!(index.contains(
+        "### `Shape`\n\n- [`Shape.square`](Shapes.md#Shape.square): The area of a square\n"
+    ));
+    assert!(index
+        .contains("### `Shape.Circle`\n\n- [`Shape.Circle.area`](Shapes.md#Shape.Circle.area)\n"));
+
+    // Breadcrumbs show the components of the namespace
+    let markdown = lean2md::convert_str(
+        "namespace A.B --#\ndef x := 1\nend A.B --#",
+        "",
+        &lean2md::Options {
+            namespaces: lean2md::NamespaceStyle::Breadcrumbs,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+    .markdown;
+    assert_eq!(markdown, "`A` › `B`\n\n```lean\ndef x := 1\n```\n");
+}
+
+#[test]
+fn test_coverage_report() {
+    let temp_in = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_in.path().join("Basics.lean"),
+        "/- # Basics\n\nSome numbers. -/\n\
+         /-- One -/\n\
+         def one := 1\n\
+         def two := 2\n\
+         private def helper := 3\n\
+         /-- Three -/ --+\n\
+         def three := 3\n",
+    )
+    .unwrap();
+    fs::write(temp_in.path().join("Code.lean"), "def four := 4\n").unwrap();
+
+    let report = lean2md::coverage_report(temp_in.path(), &lean2md::Options::default()).unwrap();
+    assert_eq!(report.declarations, 4);
+    assert_eq!(report.documented, 2);
+    assert_eq!(report.coverage, 50.0);
+
+    let basics = &report.files[0];
+    assert_eq!(basics.file, std::path::PathBuf::from("Basics.lean"));
+    assert_eq!(basics.undocumented, ["two"]);
+    assert_eq!((basics.prose_lines, basics.code_lines), (2, 5));
+
+    let mut text = Vec::new();
+    report.write_text(&mut text).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "Basics.lean: 2/3 declarations documented, prose/code 0.40\n\
+         \x20 undocumented: two\n\
+         Code.lean: 0/1 declarations documented, prose/code 0.00\n\
+         \x20 undocumented: four\n\
+         \x20 no prose\n\
+         Total: 2/4 declarations documented (50.0%)\n"
+    );
+
+    let mut json = Vec::new();
+    report.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["files"][1]["file"], "Code.lean");
+    assert_eq!(json["files"][1]["undocumented"][0], "four");
+    assert_eq!(json["coverage"], 50.0);
+}
+
+#[test]
+fn test_table_of_contents() {
+    let input = "/- # Numbers\n\n--@toc\n\n## Natural numbers -/\n\
+                 def one := 1\n\
+                 /- ### The `succ` function\n\nText. -/\n\
+                 /- ## Natural numbers -/\n\
+                 --@toc\n\
+                 def two := 2\n";
+    let markdown = lean2md::convert_str(input, "", &lean2md::Options::default())
+        .unwrap()
+        .markdown;
+    assert_eq!(
+        markdown,
+        "# Numbers\n\n\
+         - [Natural numbers](#natural-numbers)\n  \
+         - [The `succ` function](#the-succ-function)\n\
+         - [Natural numbers](#natural-numbers-1)\n\n\
+         ## Natural numbers\n\n\
+         ```lean\ndef one := 1\n```\n\n\
+         ### The `succ` function\n\nText.\n\n\
+         ## Natural numbers\n\n\
+         - [Natural numbers](#natural-numbers)\n  \
+         - [The `succ` function](#the-succ-function)\n\
+         - [Natural numbers](#natural-numbers-1)\n\n\
+         ```lean\ndef two := 2\n```\n"
+    );
+
+    // With the option, pages without a marker get one below their title
+    let options = lean2md::Options {
+        toc: true,
+        ..Default::default()
+    };
+    let markdown = lean2md::convert_str("/- # Title\n\n## A -/\n/- ## B! -/", "", &options)
+        .unwrap()
+        .markdown;
+    assert_eq!(
+        markdown,
+        "# Title\n\n- [A](#a)\n- [B!](#b)\n\n## A\n\n## B!\n"
+    );
+}
+
+#[test]
+fn test_heading_shift_and_numbering() {
+    let input = "/- ## Basics\n\n```lean\n# not a heading\n```\n\n### Terms -/\n\
+                 def one := 1\n\
+                 /- ### Types\n\n## Proofs -/\n";
+    let options = lean2md::Options {
+        heading_shift: 1,
+        numbering: lean2md::Numbering::File,
+        toc: true,
+        ..Default::default()
+    };
+    let markdown = lean2md::convert_str(input, "", &options).unwrap().markdown;
+    assert_eq!(
+        markdown,
+        "- [1 Basics](#1-basics)\n  \
+         - [1.1 Terms](#11-terms)\n  \
+         - [1.2 Types](#12-types)\n\
+         - [2 Proofs](#2-proofs)\n\n\
+         ### 1 Basics\n\n```lean\n# not a heading\n```\n\n#### 1.1 Terms\n\n\
+         ```lean\ndef one := 1\n```\n\n\
+         #### 1.2 Types\n\n### 2 Proofs\n"
+    );
+
+    // Book numbering continues in the order of SUMMARY.md
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(
+        temp_in.path().join("Intro.lean"),
+        "/- # Intro\n\n## Goals -/\n",
+    )
+    .unwrap();
+    fs::write(
+        temp_in.path().join("Logic.lean"),
+        "/- # Logic\n\n## And\n\n## Or -/\n",
+    )
+    .unwrap();
+    fs::write(temp_in.path().join(lean2md::ORDER_FILE), "Intro\nLogic\n").unwrap();
+    fs::write(temp_in.path().join("Appendix.lean"), "/- ## More -/\n").unwrap();
+
+    let options = lean2md::Options {
+        numbering: lean2md::Numbering::Book,
+        ..Default::default()
+    };
+    lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options).unwrap();
+    let read = |name: &str| fs::read_to_string(temp_out.path().join(name)).unwrap();
+    assert_eq!(read("Intro.md"), "# 1 Intro\n\n## 1.1 Goals\n");
+    assert_eq!(read("Logic.md"), "# 2 Logic\n\n## 2.1 And\n\n## 2.2 Or\n");
+    assert_eq!(read("Appendix.md"), "## 2.3 More\n");
+}
+
+#[test]
+fn test_book_numbering_reports_unparsable_sources() {
+    let temp_in = tempfile::tempdir().unwrap();
+    let temp_out = tempfile::tempdir().unwrap();
+    fs::write(temp_in.path().join("A.lean"), "/- # A -/\n").unwrap();
+    fs::write(temp_in.path().join("B.lean"), "/- unclosed").unwrap();
+
+    // Later pages would get wrong numbers, so even --keep-going stops
+    let options = lean2md::Options {
+        numbering: lean2md::Numbering::Book,
+        keep_going: true,
+        ..Default::default()
+    };
+    let error = lean2md::process_directory_with_options(temp_in.path(), temp_out.path(), &options)
+        .unwrap_err();
+
+    assert!(error.to_string().contains("B.lean"));
+    assert!(!temp_out.path().join("A.md").exists());
+}